@@ -1,3 +1,5 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
 use crate::parameters::{InitialHeading, Parameters};
 
 #[repr(C)]
@@ -5,40 +7,63 @@ use crate::parameters::{InitialHeading, Parameters};
 pub struct Agent {
     pub position: [f32; 2],
     pub velocity: [f32; 2],
+    /// Where the agent started, which it returns to with `BoundaryMode::RespawnInitial`.
+    pub initial_position: [f32; 2],
+    /// State of the agent's own PCG random number generator, advanced with every number drawn.
+    pub rng_state: u32,
+    /// Index into the species table, which is also the trail layer the agent deposits on.
+    pub species: u32,
 }
 impl Agent {
-    pub fn new_with_random_start_position(params: &Parameters) -> Self {
+    pub fn new_with_random_start_position(
+        params: &Parameters,
+        species: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
         let middle = [
             (params.shader_parameters.canvas_width / 2) as f32,
             (params.shader_parameters.canvas_height / 2) as f32,
         ];
 
-        let in_circle = random_point_in_circle(params.initial_conditions.initial_circle_radius);
+        let in_circle =
+            random_point_in_circle(params.initial_conditions.initial_circle_radius, rng);
 
         let position = [middle[0] + in_circle[0], middle[1] + in_circle[1]];
 
         let dir = match params.initial_conditions.initial_heading {
             InitialHeading::Inward => normalize(vector_from_a_to_b(position, middle)),
             InitialHeading::Outward => normalize(vector_from_a_to_b(middle, position)),
-            InitialHeading::Random => random_normalized_vector(),
+            InitialHeading::Random => random_normalized_vector(rng),
         };
 
         let velocity = dir;
 
-        Agent { position, velocity }
+        Agent {
+            position,
+            velocity,
+            initial_position: position,
+            rng_state: rng.gen(),
+            species,
+        }
     }
 }
 
 pub fn initial_agent_distribution(params: &Parameters) -> Vec<Agent> {
+    // Same seed, same distribution
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // Species take turns, so they are evenly mixed
+    let number_of_species = params.shader_parameters.number_of_species;
+
     (0..params.number_of_agents)
-        .map(|_| Agent::new_with_random_start_position(params))
+        .map(|i| Agent::new_with_random_start_position(params, i % number_of_species, &mut rng))
         .collect()
 }
 
-fn random_point_in_circle(radius: f32) -> [f32; 2] {
-    use rand::Rng as _;
-    let mut rng = rand::thread_rng();
-
+fn random_point_in_circle(radius: f32, rng: &mut impl Rng) -> [f32; 2] {
     // Randomly pick an angle between 0 and 2π.
     use std::f32::consts::PI;
     let theta: f32 = rng.gen_range(0.0..2.0 * PI);
@@ -65,10 +90,7 @@ fn vector_from_a_to_b(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
     [b[0] - a[0], b[1] - a[1]]
 }
 
-fn random_normalized_vector() -> [f32; 2] {
-    use rand::Rng as _;
-    let mut rng = rand::thread_rng();
-
+fn random_normalized_vector(rng: &mut impl Rng) -> [f32; 2] {
     // Randomly pick an angle between 0 and 2π.
     use std::f32::consts::PI;
     let theta: f32 = rng.gen_range(0.0..2.0 * PI);
@@ -0,0 +1,37 @@
+use crate::{agent::Agent, parameters::Parameters, Simulation};
+
+/// Something that can advance the simulation and hand its state back to the CPU.
+///
+/// Implemented by the wgpu `Simulation` and by the pure-Rust `cpu::CpuSimulation`, so callers can
+/// run (and compare) the same parameters on either.
+pub trait Backend {
+    fn params(&self) -> &Parameters;
+
+    /// Advances the simulation by one tick.
+    fn step(&mut self);
+
+    /// Current state of every agent, in buffer order.
+    fn agents(&self) -> Vec<Agent>;
+
+    /// Current trail map: one layer per species, each `canvas_width * canvas_height` values row by
+    /// row.
+    fn trail_map(&self) -> Vec<f32>;
+}
+
+impl Backend for Simulation {
+    fn params(&self) -> &Parameters {
+        Simulation::params(self)
+    }
+
+    fn step(&mut self) {
+        Simulation::step(self);
+    }
+
+    fn agents(&self) -> Vec<Agent> {
+        self.read_agents()
+    }
+
+    fn trail_map(&self) -> Vec<f32> {
+        self.read_trail_map()
+    }
+}
@@ -0,0 +1,99 @@
+//! Painting into the running simulation, to sculpt the network by hand.
+//!
+//! Deposit and erase paint straight into every trail layer, where diffusion and decay take over
+//! as with any other trail. Attractors and repellers paint into the food layer, so they keep
+//! stimulating (or suppressing) the trail around them every tick.
+
+use std::fmt;
+
+/// Smallest radius, which still paints a pixel and can be scaled back up.
+pub const MIN_RADIUS: f32 = 1.0;
+pub const MAX_RADIUS: f32 = 500.0;
+
+/// What the brush paints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Tool {
+    #[default]
+    Deposit,
+    Erase,
+    Attractor,
+    Repeller,
+}
+
+/// The tool in hand and how wide and strong it paints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub tool: Tool,
+    /// In canvas pixels.
+    pub radius: f32,
+    /// Trail or food added in the middle of a stroke, fading out towards the rim.
+    pub strength: f32,
+}
+
+impl Brush {
+    pub fn new(radius: f32, strength: f32) -> Self {
+        Self {
+            tool: Tool::default(),
+            radius: radius.clamp(MIN_RADIUS, MAX_RADIUS),
+            strength: strength.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Scales the radius by `factor`, within sensible bounds.
+    pub fn scale_radius(&mut self, factor: f32) {
+        self.radius = (self.radius * factor).clamp(MIN_RADIUS, MAX_RADIUS);
+    }
+
+    /// Changes the strength by `delta`, staying between 0 and 1.
+    pub fn adjust_strength(&mut self, delta: f32) {
+        self.strength = (self.strength + delta).clamp(0.0, 1.0);
+    }
+
+    /// One dab of the brush around canvas position `(x, y)`.
+    pub fn stroke(&self, x: f32, y: f32) -> BrushStroke {
+        let (trail_strength, food_strength) = match self.tool {
+            Tool::Deposit => (self.strength, 0.0),
+            Tool::Erase => (-self.strength, 0.0),
+            Tool::Attractor => (0.0, self.strength),
+            Tool::Repeller => (0.0, -self.strength),
+        };
+
+        BrushStroke {
+            x,
+            y,
+            radius: self.radius,
+            trail_strength,
+            food_strength,
+        }
+    }
+}
+
+impl fmt::Display for Brush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}, radius {:.1}, strength {:.2}",
+            self.tool, self.radius, self.strength
+        )
+    }
+}
+
+/// Uniform the paint pass reads, bound after the simulation resources.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Zeroable, bytemuck::NoUninit)]
+pub struct BrushStroke {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    /// Added to every trail layer, negative to erase.
+    pub trail_strength: f32,
+    /// Added to the food layer, negative for repellers.
+    pub food_strength: f32,
+}
+
+impl BrushStroke {
+    /// Pixels the stroke covers along either axis, which the paint pass is dispatched over.
+    pub fn span(&self) -> u32 {
+        2 * self.radius.max(0.0).ceil() as u32 + 1
+    }
+}
@@ -0,0 +1,248 @@
+//! Turning trail maps into images outside of the render pipeline, e.g. to write frames of a
+//! headless run to disk.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use image::{Rgb, RgbImage};
+
+use crate::parameters::ShaderParameters;
+
+/// Destination for the frames of a run: a directory of images, a video stream, ...
+pub trait FrameSink {
+    /// Adds a frame showing `trail_map`, which holds a layer covering the whole canvas described
+    /// by `ctx` for every species.
+    fn write_frame(&mut self, ctx: &ShaderParameters, trail_map: &[f32]) -> io::Result<()>;
+
+    /// Flushes whatever the sink still buffers. No frames may be written afterwards.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Width and height of the frames a sink writes, which need not match the canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameSize {
+    pub fn of_canvas(ctx: &ShaderParameters) -> Self {
+        Self {
+            width: ctx.canvas_width,
+            height: ctx.canvas_height,
+        }
+    }
+}
+
+/// Scales every layer of the trail map to `size`. Each output pixel averages the canvas pixels it
+/// covers, so downscaled frames don't lose thin veins to aliasing.
+pub fn resample(ctx: &ShaderParameters, trail_map: &[f32], size: FrameSize) -> Vec<f32> {
+    if size == FrameSize::of_canvas(ctx) {
+        return trail_map.to_vec();
+    }
+
+    let layer_len = (ctx.canvas_width * ctx.canvas_height) as usize;
+    trail_map
+        .chunks(layer_len)
+        .flat_map(|layer| resample_layer(ctx, layer, size))
+        .collect()
+}
+
+fn resample_layer(ctx: &ShaderParameters, trail_map: &[f32], size: FrameSize) -> Vec<f32> {
+    let scale_x = ctx.canvas_width as f32 / size.width as f32;
+    let scale_y = ctx.canvas_height as f32 / size.height as f32;
+
+    let mut resampled = Vec::with_capacity((size.width * size.height) as usize);
+    for y in 0..size.height {
+        let (y0, y1) = covered_range(y, scale_y, ctx.canvas_height);
+        for x in 0..size.width {
+            let (x0, x1) = covered_range(x, scale_x, ctx.canvas_width);
+
+            let mut sum = 0.0;
+            for yi in y0..y1 {
+                for xi in x0..x1 {
+                    sum += trail_map[(yi * ctx.canvas_width + xi) as usize];
+                }
+            }
+            resampled.push(sum / ((x1 - x0) * (y1 - y0)) as f32);
+        }
+    }
+
+    resampled
+}
+
+/// Range of canvas pixels covered by output pixel `i`, at least one pixel wide.
+fn covered_range(i: u32, scale: f32, len: u32) -> (u32, u32) {
+    let start = ((i as f32 * scale) as u32).min(len - 1);
+    let end = (((i + 1) as f32 * scale) as u32).clamp(start + 1, len);
+    (start, end)
+}
+
+/// Colours a trail map of `size` the same way `fragment_main` does.
+pub fn render_frame(ctx: &ShaderParameters, trail_map: &[f32], size: FrameSize) -> RgbImage {
+    let layer_len = (size.width * size.height) as usize;
+
+    RgbImage::from_fn(size.width, size.height, |x, y| {
+        let pixel_idx = (y * size.width + x) as usize;
+        Rgb(pixel_color(ctx, trail_map, layer_len, pixel_idx).map(linear_to_srgb))
+    })
+}
+
+fn pixel_color(
+    ctx: &ShaderParameters,
+    trail_map: &[f32],
+    layer_len: usize,
+    idx: usize,
+) -> [f32; 3] {
+    if ctx.bool_enable_render_trail_map != 0 && ctx.number_of_species > 1 {
+        return mix_species(ctx, trail_map, layer_len, idx);
+    }
+
+    color(ctx, trail_map[idx])
+}
+
+/// Colour of a trail map value as it appears on screen.
+///
+/// The window presents to an sRGB surface, so the colours are sRGB encoded here to come out the
+/// way they look there.
+pub fn color_srgb(ctx: &ShaderParameters, v: f32) -> [u8; 3] {
+    color(ctx, v).map(linear_to_srgb)
+}
+
+fn color(ctx: &ShaderParameters, v: f32) -> [f32; 3] {
+    let red = [1.0, 0.0, 0.0];
+
+    if ctx.bool_enable_render_trail_map == 0 {
+        return red;
+    }
+
+    if v > 1.0 {
+        // Not supposed to happen
+        return red;
+    }
+
+    if ctx.bool_enable_color != 0 {
+        gradient(v)
+    } else {
+        [v, v, v]
+    }
+}
+
+/// Port of `mix_species` in `shader.wgsl`.
+fn mix_species(
+    ctx: &ShaderParameters,
+    trail_map: &[f32],
+    layer_len: usize,
+    idx: usize,
+) -> [f32; 3] {
+    let mut color = [0.0; 3];
+    for layer in 0..ctx.number_of_species {
+        let v = trail_map[layer as usize * layer_len + idx];
+        let layer_color = if ctx.bool_enable_color != 0 {
+            species_color(layer)
+        } else {
+            [1.0; 3]
+        };
+        for (c, layer_c) in color.iter_mut().zip(layer_color) {
+            *c += v * layer_c;
+        }
+    }
+    color.map(|c| c.min(1.0))
+}
+
+fn species_color(layer: u32) -> [f32; 3] {
+    match layer {
+        0 => rgb(246, 255, 0),
+        1 => rgb(0, 221, 255),
+        2 => rgb(255, 0, 170),
+        _ => rgb(255, 128, 0),
+    }
+}
+
+/// Port of `gradient` in `shader.wgsl`.
+pub fn gradient(t: f32) -> [f32; 3] {
+    let black = [0.0, 0.0, 0.0];
+    let g1 = rgb(246, 255, 0);
+    let g2 = rgb(200, 255, 0);
+    let g3 = rgb(149, 255, 0);
+    let g4 = rgb(77, 255, 0);
+    let g5 = rgb(0, 221, 255);
+
+    if t > 1.0 {
+        return black;
+    }
+
+    let idx = (t * 5.0).floor();
+    let segment_length = 1.0 / 5.0;
+
+    let (c1, c2) = if t <= 0.2 {
+        (black, g1)
+    } else if t <= 0.4 {
+        (g1, g2)
+    } else if t <= 0.6 {
+        (g2, g3)
+    } else if t <= 0.8 {
+        (g3, g4)
+    } else {
+        (g4, g5)
+    };
+
+    if idx >= 5.0 {
+        return c2;
+    }
+
+    let t_segment = (t / segment_length) - idx;
+    [0, 1, 2].map(|i| c1[i] + t_segment * (c2[i] - c1[i]))
+}
+
+fn rgb(r: u8, g: u8, b: u8) -> [f32; 3] {
+    [r, g, b].map(|c| f32::from(c) / 255.0)
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let encoded = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Writes numbered PNG files (`frame_000000.png`, `frame_000001.png`, ...) into a directory.
+pub struct PngSequence {
+    directory: PathBuf,
+    size: Option<FrameSize>,
+    next_frame: u32,
+}
+
+impl PngSequence {
+    /// Frames are written at canvas size unless `size` is given.
+    pub fn new(directory: impl AsRef<Path>, size: Option<FrameSize>) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            size,
+            next_frame: 0,
+        }
+    }
+}
+
+impl FrameSink for PngSequence {
+    fn write_frame(&mut self, ctx: &ShaderParameters, trail_map: &[f32]) -> io::Result<()> {
+        let size = self.size.unwrap_or(FrameSize::of_canvas(ctx));
+        let frame = render_frame(ctx, &resample(ctx, trail_map, size), size);
+
+        let path = self
+            .directory
+            .join(format!("frame_{:06}.png", self.next_frame));
+        frame.save(path).map_err(io::Error::other)?;
+
+        self.next_frame += 1;
+
+        Ok(())
+    }
+}
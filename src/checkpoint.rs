@@ -0,0 +1,484 @@
+//! Saving the complete state of a run to a file and picking it up again later.
+//!
+//! A checkpoint file is laid out as follows, all integers little endian:
+//!
+//! | bytes    | content                                           |
+//! |----------|---------------------------------------------------|
+//! | 8        | magic `PHYSARUM`                                  |
+//! | 4        | format version                                    |
+//! | 4 + n    | length and JSON of the `Parameters` (incl. seed)  |
+//! | 4        | number of ticks simulated                         |
+//! | 8 + n    | number and contents of the agent buffer           |
+//! | 8 + n    | number and contents of the trail map              |
+//!
+//! Buffer contents are stored exactly as they are laid out for the GPU.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    agent::Agent,
+    backend::Backend,
+    capture::{self, FrameSize},
+    parameters::Parameters,
+};
+
+const MAGIC: &[u8; 8] = b"PHYSARUM";
+
+/// Bump whenever the layout of the file, `Agent` or the trail map changes.
+pub const VERSION: u32 = 4;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    NotACheckpoint,
+    UnsupportedVersion(u32),
+    Parameters(String),
+    Mismatch(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::NotACheckpoint => write!(f, "not a checkpoint file"),
+            Error::UnsupportedVersion(version) => write!(
+                f,
+                "checkpoint has version {}, but only version {} is supported",
+                version, VERSION
+            ),
+            Error::Parameters(e) => write!(f, "invalid parameters in checkpoint: {}", e),
+            Error::Mismatch(e) => write!(f, "checkpoint does not fit the parameters: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Everything needed to continue a run where it was left.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub params: Parameters,
+    pub agents: Vec<Agent>,
+    pub trail_map: Vec<f32>,
+}
+
+impl Checkpoint {
+    pub fn capture(backend: &dyn Backend) -> Self {
+        Self {
+            params: backend.params().clone(),
+            agents: backend.agents(),
+            trail_map: backend.trail_map(),
+        }
+    }
+
+    /// Continues from the same state with different parameters, to branch off experiments.
+    ///
+    /// The agent buffer, trail map and tick are kept as they are, so the number of agents, the
+    /// canvas size and the number of species must stay the same.
+    pub fn with_parameters(self, mut params: Parameters) -> Result<Self, Error> {
+        params.tick = self.params.tick;
+
+        let checkpoint = Self { params, ..self };
+        checkpoint.validate()?;
+        Ok(checkpoint)
+    }
+
+    /// Continues from the same state with different parameters like `with_parameters`, first
+    /// resizing it to their canvas if it has another size.
+    pub fn fitted_to(self, params: Parameters) -> Result<Self, Error> {
+        let ctx = &params.shader_parameters;
+        let saved = &self.params.shader_parameters;
+        let checkpoint =
+            if (saved.canvas_width, saved.canvas_height) == (ctx.canvas_width, ctx.canvas_height) {
+                self
+            } else {
+                self.resized(ctx.canvas_width, ctx.canvas_height)
+            };
+
+        checkpoint.with_parameters(params)
+    }
+
+    /// Continues on a canvas of another size, resampling the trail map and moving every agent to
+    /// the same relative position.
+    ///
+    /// Food and obstacles given in pixels stay where they are, while images stretch with the
+    /// canvas.
+    pub fn resized(self, width: u32, height: u32) -> Self {
+        let ctx = &self.params.shader_parameters;
+        let scale = [
+            width as f32 / ctx.canvas_width as f32,
+            height as f32 / ctx.canvas_height as f32,
+        ];
+        let rescale = |[x, y]: [f32; 2]| {
+            [
+                (x * scale[0]).min(width as f32 - 1.0),
+                (y * scale[1]).min(height as f32 - 1.0),
+            ]
+        };
+
+        let trail_map = capture::resample(ctx, &self.trail_map, FrameSize { width, height });
+
+        let agents = self
+            .agents
+            .into_iter()
+            .map(|agent| Agent {
+                position: rescale(agent.position),
+                initial_position: rescale(agent.initial_position),
+                ..agent
+            })
+            .collect();
+
+        let mut params = self.params;
+        params.shader_parameters.canvas_width = width;
+        params.shader_parameters.canvas_height = height;
+
+        Self {
+            params,
+            agents,
+            trail_map,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        let params =
+            serde_json::to_vec(&self.params).map_err(|e| Error::Parameters(e.to_string()))?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        writer.write_all(&(params.len() as u32).to_le_bytes())?;
+        writer.write_all(&params)?;
+        writer.write_all(&self.params.tick.to_le_bytes())?;
+
+        writer.write_all(&(self.agents.len() as u64).to_le_bytes())?;
+        writer.write_all(bytemuck::cast_slice(&self.agents))?;
+
+        writer.write_all(&(self.trail_map.len() as u64).to_le_bytes())?;
+        writer.write_all(bytemuck::cast_slice(&self.trail_map))?;
+
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::NotACheckpoint);
+        }
+
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let params_length = read_u32(reader)? as usize;
+        let params = read_bytes(reader, params_length)?;
+        let mut params: Parameters =
+            serde_json::from_slice(&params).map_err(|e| Error::Parameters(e.to_string()))?;
+        params.tick = read_u32(reader)?;
+
+        // Lengths are checked against the parameters before reading, so a corrupt file is
+        // reported rather than read as far as it claims to go
+        let number_of_agents = read_u64(reader)?;
+        check_agents(&params, number_of_agents)?;
+        let agents = bytemuck::pod_collect_to_vec(&read_bytes(
+            reader,
+            byte_length::<Agent>(number_of_agents)?,
+        )?);
+
+        let trail_map_length = read_u64(reader)?;
+        check_trail_map(&params, trail_map_length)?;
+        let trail_map = bytemuck::pod_collect_to_vec(&read_bytes(
+            reader,
+            byte_length::<f32>(trail_map_length)?,
+        )?);
+
+        Ok(Self {
+            params,
+            agents,
+            trail_map,
+        })
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        check_agents(&self.params, self.agents.len() as u64)?;
+        check_trail_map(&self.params, self.trail_map.len() as u64)
+    }
+}
+
+fn check_agents(params: &Parameters, number_of_agents: u64) -> Result<(), Error> {
+    if number_of_agents != params.number_of_agents as u64 {
+        return Err(Error::Mismatch(format!(
+            "{} agents stored, {} expected",
+            number_of_agents, params.number_of_agents
+        )));
+    }
+    Ok(())
+}
+
+fn check_trail_map(params: &Parameters, length: u64) -> Result<(), Error> {
+    // Computed without overflowing, as the canvas size may come from a corrupt file
+    let ctx = &params.shader_parameters;
+    let expected = (ctx.canvas_width as u64 * ctx.canvas_height as u64)
+        .checked_mul(ctx.number_of_species as u64);
+
+    if expected != Some(length) {
+        return Err(Error::Mismatch(format!(
+            "trail map of {} values stored, {}x{} for {} species expected",
+            length, ctx.canvas_width, ctx.canvas_height, ctx.number_of_species
+        )));
+    }
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn byte_length<T>(count: u64) -> Result<usize, Error> {
+    usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(std::mem::size_of::<T>()))
+        .ok_or_else(|| Error::Mismatch(format!("{} values do not fit in memory", count)))
+}
+
+/// Grows with what is actually read, so it never allocates more than the reader holds.
+fn read_bytes(reader: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::ShaderParameters;
+
+    fn small_checkpoint() -> Checkpoint {
+        let mut params = Parameters::builder()
+            .number_of_agents(3)
+            .seed(Some(9))
+            .shader_parameters(ShaderParameters {
+                canvas_width: 4,
+                canvas_height: 2,
+                number_of_species: 2,
+                ..Default::default()
+            })
+            .tick(17)
+            .build();
+        params.attraction[1][0] = 0.25;
+
+        let agents = (0..3)
+            .map(|i| Agent {
+                position: [i as f32, 1.5],
+                velocity: [0.0, -1.0],
+                initial_position: [2.0, 1.0],
+                rng_state: 1000 + i,
+                species: i % 2,
+            })
+            .collect();
+        let trail_map = (0..16).map(|i| i as f32 / 16.0).collect();
+
+        Checkpoint {
+            params,
+            agents,
+            trail_map,
+        }
+    }
+
+    fn to_bytes(checkpoint: &Checkpoint) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        checkpoint.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let checkpoint = small_checkpoint();
+        let read = Checkpoint::read_from(&mut to_bytes(&checkpoint).as_slice()).unwrap();
+
+        assert_eq!(read.params, checkpoint.params);
+        assert_eq!(read.params.tick, 17);
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&read.agents),
+            bytemuck::cast_slice::<Agent, u8>(&checkpoint.agents)
+        );
+        assert_eq!(read.trail_map, checkpoint.trail_map);
+    }
+
+    #[test]
+    fn lays_out_the_bytes_as_documented() {
+        let checkpoint = small_checkpoint();
+        let bytes = to_bytes(&checkpoint);
+        let params = serde_json::to_vec(&checkpoint.params).unwrap();
+
+        assert_eq!(&bytes[..8], MAGIC);
+        assert_eq!(bytes[8..12], VERSION.to_le_bytes());
+        assert_eq!(bytes[12..16], (params.len() as u32).to_le_bytes());
+        assert_eq!(bytes[16..16 + params.len()], params);
+
+        let rest = &bytes[16 + params.len()..];
+        assert_eq!(rest[..4], 17u32.to_le_bytes());
+        assert_eq!(rest[4..12], 3u64.to_le_bytes());
+
+        let agents_len = 3 * std::mem::size_of::<Agent>();
+        assert_eq!(
+            rest[12..12 + agents_len],
+            *bytemuck::cast_slice::<Agent, u8>(&checkpoint.agents)
+        );
+
+        let rest = &rest[12 + agents_len..];
+        assert_eq!(rest[..8], 16u64.to_le_bytes());
+        assert_eq!(
+            rest[8..],
+            *bytemuck::cast_slice::<f32, u8>(&checkpoint.trail_map)
+        );
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let bytes = to_bytes(&small_checkpoint());
+
+        let mut not_a_checkpoint = bytes.clone();
+        not_a_checkpoint[0] = b'X';
+        assert!(matches!(
+            Checkpoint::read_from(&mut not_a_checkpoint.as_slice()),
+            Err(Error::NotACheckpoint)
+        ));
+
+        let mut other_version = bytes.clone();
+        other_version[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Checkpoint::read_from(&mut other_version.as_slice()),
+            Err(Error::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(
+            Checkpoint::read_from(&mut &truncated[..]),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_mismatched_agents_and_trail_map() {
+        let mut too_few_agents = small_checkpoint();
+        too_few_agents.agents.pop();
+        assert!(matches!(
+            Checkpoint::read_from(&mut to_bytes(&too_few_agents).as_slice()),
+            Err(Error::Mismatch(_))
+        ));
+
+        let mut short_trail_map = small_checkpoint();
+        short_trail_map.trail_map.truncate(8);
+        assert!(matches!(
+            Checkpoint::read_from(&mut to_bytes(&short_trail_map).as_slice()),
+            Err(Error::Mismatch(_))
+        ));
+
+        let checkpoint = small_checkpoint();
+        let mut one_species = checkpoint.params.clone();
+        one_species.shader_parameters.number_of_species = 1;
+        assert!(matches!(
+            checkpoint.with_parameters(one_species),
+            Err(Error::Mismatch(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_lengths() {
+        let checkpoint = small_checkpoint();
+        let bytes = to_bytes(&checkpoint);
+        let params_len = serde_json::to_vec(&checkpoint.params).unwrap().len();
+        let agents_at = 16 + params_len + 4;
+        let trail_map_at = agents_at + 8 + 3 * std::mem::size_of::<Agent>();
+
+        let mut many_agents = bytes.clone();
+        many_agents[agents_at..agents_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            Checkpoint::read_from(&mut many_agents.as_slice()),
+            Err(Error::Mismatch(_))
+        ));
+
+        let mut long_trail_map = bytes.clone();
+        long_trail_map[trail_map_at..trail_map_at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            Checkpoint::read_from(&mut long_trail_map.as_slice()),
+            Err(Error::Mismatch(_))
+        ));
+
+        // A canvas too large to hold in memory claims a matching, overflowing trail map
+        let mut huge = checkpoint.clone();
+        let ctx = &mut huge.params.shader_parameters;
+        ctx.canvas_width = u32::MAX;
+        ctx.canvas_height = u32::MAX;
+        ctx.number_of_species = 4;
+        assert!(matches!(
+            Checkpoint::read_from(&mut to_bytes(&huge).as_slice()),
+            Err(Error::Mismatch(_))
+        ));
+        assert!(byte_length::<f32>(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn fits_onto_a_resized_canvas() {
+        let checkpoint = small_checkpoint();
+        let mut resized = checkpoint.params.clone();
+        resized.shader_parameters.canvas_width = 8;
+        resized.shader_parameters.canvas_height = 6;
+        resized.shader_parameters.agent_speed = 0.5;
+        resized.tick = 0;
+
+        assert!(matches!(
+            checkpoint.clone().with_parameters(resized.clone()),
+            Err(Error::Mismatch(_))
+        ));
+
+        let restored = checkpoint.clone().fitted_to(resized.clone()).unwrap();
+        assert_eq!(restored.params.shader_parameters, resized.shader_parameters);
+        assert_eq!(restored.params.tick, 17);
+        assert_eq!(restored.agents.len(), 3);
+        assert_eq!(restored.agents[2].position, [4.0, 4.5]);
+        assert_eq!(restored.trail_map.len(), 8 * 6 * 2);
+
+        // Back at the size it was saved at, nothing is resampled
+        let unchanged = checkpoint
+            .clone()
+            .fitted_to(checkpoint.params.clone())
+            .unwrap();
+        assert_eq!(unchanged.trail_map, checkpoint.trail_map);
+    }
+}
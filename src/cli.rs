@@ -0,0 +1,321 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use physarum::{
+    brush,
+    capture::FrameSize,
+    checkpoint::Checkpoint,
+    config,
+    parameters::{FoodSource, Obstacle, Parameters, ShaderParameters, MAX_SPECIES},
+    presets,
+};
+
+/// Agent-based simulation of the Physarum polycephalum slime mould.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Directory user presets are saved to and loaded from [default: $PHYSARUM_PRESETS, or
+    /// physarum/presets in $XDG_CONFIG_HOME or ~/.config]
+    #[arg(long, global = true)]
+    pub preset_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Parses the command line like [`Parser::parse`], also rejecting flags that cannot go together.
+    pub fn parse_valid() -> Self {
+        let cli = Self::parse();
+        if let Command::Render {
+            format: FrameFormat::Png,
+            stdout: true,
+            ..
+        } = cli.command
+        {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "the argument '--stdout' cannot be used with '--format png'",
+                )
+                .exit();
+        }
+        cli
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the simulation in a window
+    View {
+        #[command(flatten)]
+        simulation: SimulationArgs,
+
+        /// Resample the grid to the window size on resize instead of letterboxing the canvas
+        #[arg(long)]
+        resample_grid: bool,
+
+        /// Radius in canvas pixels of the brush painted with the right mouse button, between 1 and
+        /// 500
+        #[arg(long, default_value_t = 8.0, value_parser = parse_brush_radius)]
+        brush_radius: f32,
+
+        /// Trail or food the brush adds in the middle of a stroke, between 0 and 1
+        #[arg(long, default_value_t = 0.5, value_parser = parse_brush_strength)]
+        brush_strength: f32,
+
+        /// File of key bindings (.toml, .json or .ron) replacing the built-in ones
+        #[arg(long)]
+        keys: Option<PathBuf>,
+
+        /// Start with the parameter panel hidden. F1 shows it
+        #[arg(long)]
+        hide_gui: bool,
+    },
+
+    /// Run the simulation without a window and write its frames to the output directory
+    Render {
+        #[command(flatten)]
+        simulation: SimulationArgs,
+
+        /// Number of ticks to simulate
+        #[arg(long, default_value_t = 1000)]
+        ticks: u32,
+
+        /// Write a frame every this many ticks [default: only the last tick]
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        every: Option<u32>,
+
+        /// What to write the frames as
+        #[arg(long, value_enum, default_value_t = FrameFormat::Png)]
+        format: FrameFormat,
+
+        /// Size of the written frames as WIDTHxHEIGHT [default: canvas size]
+        #[arg(long, value_parser = parse_frame_size)]
+        size: Option<FrameSize>,
+
+        /// Playback rate of y4m and gif output. Gif plays at most 100 frames per second
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+        fps: u32,
+
+        /// Write y4m or gif output to stdout instead of the output directory
+        #[arg(long)]
+        stdout: bool,
+
+        /// Also write a checkpoint of the last tick to the output directory
+        #[arg(long)]
+        checkpoint: bool,
+
+        /// Where to run the simulation
+        #[arg(long, value_enum, default_value_t = BackendKind::Gpu)]
+        backend: BackendKind,
+    },
+
+    /// Measure how many ticks per second the simulation manages
+    Bench {
+        #[command(flatten)]
+        simulation: SimulationArgs,
+
+        /// Number of ticks to simulate
+        #[arg(long, default_value_t = 1000)]
+        ticks: u32,
+
+        /// Where to run the simulation
+        #[arg(long, value_enum, default_value_t = BackendKind::Gpu)]
+        backend: BackendKind,
+    },
+
+    /// List the bundled and saved presets, or save parameters as a preset
+    Presets {
+        #[command(subcommand)]
+        command: PresetsCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PresetsCommand {
+    /// List every preset --preset accepts
+    List,
+
+    /// Save the parameters the other flags add up to in the user preset directory
+    Save {
+        /// Name to save the preset under, replacing any saved preset of that name
+        name: String,
+
+        #[command(flatten)]
+        simulation: Box<SimulationArgs>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FrameFormat {
+    /// Numbered PNG files
+    Png,
+    /// YUV4MPEG2 video stream
+    Y4m,
+    /// Animated GIF
+    Gif,
+}
+
+fn parse_frame_size(s: &str) -> Result<FrameSize, String> {
+    let invalid = || format!("'{}' is not of the form WIDTHxHEIGHT", s);
+
+    let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok(FrameSize { width, height })
+}
+
+fn parse_brush_radius(s: &str) -> Result<f32, String> {
+    parse_within(s, brush::MIN_RADIUS, brush::MAX_RADIUS)
+}
+
+fn parse_brush_strength(s: &str) -> Result<f32, String> {
+    parse_within(s, 0.0, 1.0)
+}
+
+fn parse_within(s: &str, min: f32, max: f32) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!(
+            "'{}' is not a number between {} and {}",
+            s, min, max
+        )),
+    }
+}
+
+fn parse_tick_rate(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("'{}' is not a positive number", s)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BackendKind {
+    Gpu,
+    Cpu,
+}
+
+/// Flags shared by the subcommands that set up a simulation. Anything not given comes from the
+/// config file or preset, or the checkpoint resumed from, or from the defaults in `Parameters` when
+/// there is neither.
+#[derive(Debug, Args)]
+pub struct SimulationArgs {
+    /// Config file (.toml, .json or .ron) to start from
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Preset to start from instead of a config file. `presets list` shows them all
+    #[arg(long, conflicts_with = "config")]
+    pub preset: Option<String>,
+
+    /// Checkpoint to continue from. Combine with --config or --preset to branch off with other
+    /// parameters
+    #[arg(long)]
+    pub resume: Option<PathBuf>,
+
+    /// Canvas width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Canvas height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Number of agents
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub agents: Option<u32>,
+
+    /// Number of species the agents are split into, at most 4
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=MAX_SPECIES as i64))]
+    pub species: Option<u32>,
+
+    /// Seed for the initial agent distribution
+    // Limited to what a TOML config, e.g. the parameters.toml written by render, can hold
+    #[arg(long, value_parser = clap::value_parser!(u64).range(..=i64::MAX as u64))]
+    pub seed: Option<u64>,
+
+    /// CSV file of x,y[,radius] food positions, in addition to the configured food. Repeatable
+    #[arg(long)]
+    pub food: Vec<PathBuf>,
+
+    /// Image whose dark pixels are walls, in addition to the configured obstacles. Repeatable
+    #[arg(long)]
+    pub mask: Vec<PathBuf>,
+
+    /// Number of ticks of the simulation to target per second
+    #[arg(long, value_parser = parse_tick_rate)]
+    pub tick_rate: Option<f32>,
+
+    /// Directory results are written to
+    #[arg(short, long, default_value = "output")]
+    pub output: PathBuf,
+}
+
+impl SimulationArgs {
+    /// Parameters to run with and, when resuming, the state to start from.
+    /// Presets are looked up in `preset_dir` before the bundled ones.
+    pub fn load(
+        &self,
+        preset_dir: Option<&Path>,
+    ) -> Result<(Parameters, Option<Checkpoint>), Box<dyn Error>> {
+        let checkpoint = self.resume.as_ref().map(Checkpoint::load).transpose()?;
+
+        let base = match (&self.config, &self.preset, &checkpoint) {
+            (Some(path), _, _) => config::load(path)?,
+            (None, Some(name), _) => presets::load(name, preset_dir)?,
+            (None, None, Some(checkpoint)) => checkpoint.params.clone(),
+            (None, None, None) => Parameters::default(),
+        };
+
+        let params = self.override_parameters(base);
+        let checkpoint = checkpoint
+            .map(|checkpoint| checkpoint.with_parameters(params.clone()))
+            .transpose()?;
+
+        Ok((params, checkpoint))
+    }
+
+    fn override_parameters(&self, base: Parameters) -> Parameters {
+        let mut initial_conditions = base.initial_conditions;
+        initial_conditions
+            .food
+            .extend(self.food.iter().map(|path| FoodSource::Csv {
+                path: path.clone(),
+                radius: 0.0,
+            }));
+        initial_conditions
+            .obstacles
+            .extend(self.mask.iter().map(|path| Obstacle::Image {
+                path: path.clone(),
+                invert: false,
+            }));
+
+        Parameters::builder()
+            .target_ticks_per_second(self.tick_rate.unwrap_or(base.target_ticks_per_second))
+            .number_of_agents(self.agents.unwrap_or(base.number_of_agents))
+            .seed(self.seed.or(base.seed))
+            .initial_conditions(initial_conditions)
+            .shader_parameters(ShaderParameters {
+                canvas_width: self.width.unwrap_or(base.shader_parameters.canvas_width),
+                canvas_height: self.height.unwrap_or(base.shader_parameters.canvas_height),
+                number_of_species: self
+                    .species
+                    .unwrap_or(base.shader_parameters.number_of_species),
+                ..base.shader_parameters
+            })
+            .species(base.species)
+            .attraction(base.attraction)
+            .diffusion_kernel(base.diffusion_kernel)
+            .build()
+    }
+}
@@ -0,0 +1,249 @@
+//! Reading and writing `Parameters` from and to config files.
+//!
+//! The format is picked from the file extension (`.toml`, `.json` or `.ron`). Fields missing from a
+//! file fall back to the same defaults as `Parameters::builder()`.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::parameters::Parameters;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Ron,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "ron" => Some(Self::Ron),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    UnknownFormat(String),
+    Parse(String),
+    Serialize(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::UnknownFormat(path) => write!(
+                f,
+                "cannot tell the config format of '{}' (expected .toml, .json or .ron)",
+                path
+            ),
+            Error::Parse(e) => write!(f, "invalid config: {}", e),
+            Error::Serialize(e) => write!(f, "cannot serialize config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Parameters, Error> {
+    let path = path.as_ref();
+    let format = format_of(path)?;
+    from_str(&fs::read_to_string(path)?, format)
+}
+
+pub fn save(params: &Parameters, path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let format = format_of(path)?;
+    fs::write(path, to_string(params, format)?)?;
+    Ok(())
+}
+
+pub fn from_str(s: &str, format: Format) -> Result<Parameters, Error> {
+    match format {
+        Format::Toml => toml::from_str(s).map_err(|e| Error::Parse(e.to_string())),
+        Format::Json => serde_json::from_str(s).map_err(|e| Error::Parse(e.to_string())),
+        Format::Ron => ron::from_str(s).map_err(|e| Error::Parse(e.to_string())),
+    }
+}
+
+pub fn to_string(params: &Parameters, format: Format) -> Result<String, Error> {
+    match format {
+        Format::Toml => toml::to_string_pretty(params).map_err(|e| Error::Serialize(e.to_string())),
+        Format::Json => {
+            serde_json::to_string_pretty(params).map_err(|e| Error::Serialize(e.to_string()))
+        }
+        Format::Ron => ron::ser::to_string_pretty(params, ron::ser::PrettyConfig::default())
+            .map_err(|e| Error::Serialize(e.to_string())),
+    }
+}
+
+fn format_of(path: &Path) -> Result<Format, Error> {
+    Format::from_path(path).ok_or_else(|| Error::UnknownFormat(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::{
+        BoundaryMode, DiffusionKernel, FoodSource, InitialConditions, InitialHeading, Obstacle,
+        ShaderParameters, MAX_SPECIES,
+    };
+
+    /// Parameters that differ from the defaults wherever they can.
+    fn unusual_parameters() -> Parameters {
+        let mut params = Parameters::builder()
+            .target_ticks_per_second(30.0)
+            .number_of_agents(1234)
+            .seed(Some(42))
+            .initial_conditions(InitialConditions {
+                initial_circle_radius: 12.5,
+                initial_heading: InitialHeading::Outward,
+                food: vec![FoodSource::Disc {
+                    x: 1.0,
+                    y: 2.0,
+                    radius: 3.0,
+                }],
+                obstacles: vec![Obstacle::Polygon {
+                    points: vec![[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]],
+                }],
+            })
+            .shader_parameters(ShaderParameters {
+                canvas_width: 320,
+                canvas_height: 200,
+                agent_speed: 2.5,
+                boundary_mode: BoundaryMode::RespawnInitial,
+                bool_enable_color: 0,
+                number_of_species: 2,
+                ..Default::default()
+            })
+            .diffusion_kernel(DiffusionKernel::Gaussian { sigma: 1.5 })
+            .build();
+        params.species[0].sensor_distance = 7.0;
+        params.attraction[0][1] = -0.5;
+        params
+    }
+
+    #[test]
+    fn round_trips_in_every_format() {
+        let params = unusual_parameters();
+
+        for format in [Format::Toml, Format::Json, Format::Ron] {
+            let serialized = to_string(&params, format).unwrap();
+            let deserialized = from_str(&serialized, format).unwrap_or_else(|e| {
+                panic!("{:?} does not read back: {}\n{}", format, e, serialized)
+            });
+            assert_eq!(deserialized, params, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn partial_files_fall_back_to_defaults() {
+        let toml = "number_of_agents = 10\n\n[shader_parameters]\nagent_speed = 2.0\n";
+        let json = r#"{"number_of_agents": 10, "shader_parameters": {"agent_speed": 2.0}}"#;
+        let ron = "(number_of_agents: 10, shader_parameters: (agent_speed: 2.0))";
+
+        let expected = Parameters::builder()
+            .number_of_agents(10)
+            .shader_parameters(ShaderParameters {
+                agent_speed: 2.0,
+                ..Default::default()
+            })
+            .build();
+
+        assert_eq!(from_str(toml, Format::Toml).unwrap(), expected);
+        assert_eq!(from_str(json, Format::Json).unwrap(), expected);
+        assert_eq!(from_str(ron, Format::Ron).unwrap(), expected);
+        assert_eq!(from_str("", Format::Toml).unwrap(), Parameters::default());
+    }
+
+    #[test]
+    fn enums_are_snake_case() {
+        let params = unusual_parameters();
+        let toml = to_string(&params, Format::Toml).unwrap();
+
+        assert!(
+            toml.contains(r#"boundary_mode = "respawn_initial""#),
+            "{}",
+            toml
+        );
+        assert!(toml.contains(r#"initial_heading = "outward""#), "{}", toml);
+        assert!(toml.contains(r#"diffusion_edge_mode = "zero""#), "{}", toml);
+
+        // As they were first written, e.g. in older checkpoints
+        let old = "[shader_parameters]\nboundary_mode = \"RespawnInitial\"\n";
+        assert_eq!(
+            from_str(old, Format::Toml)
+                .unwrap()
+                .shader_parameters
+                .boundary_mode,
+            BoundaryMode::RespawnInitial
+        );
+    }
+
+    #[test]
+    fn maps_agent_bounce_to_a_boundary_mode() {
+        let boundary_mode =
+            |s: &str| from_str(s, Format::Toml).map(|p| p.shader_parameters.boundary_mode);
+
+        let bounce = "[shader_parameters]\nbool_enable_agent_bounce = 1\n";
+        let no_bounce = "[shader_parameters]\nbool_enable_agent_bounce = 0\nagent_speed = 2.0\n";
+        assert_eq!(boundary_mode(bounce).unwrap(), BoundaryMode::Reflect);
+        assert_eq!(boundary_mode(no_bounce).unwrap(), BoundaryMode::Absorb);
+        assert_eq!(
+            from_str(no_bounce, Format::Toml)
+                .unwrap()
+                .shader_parameters
+                .agent_speed,
+            2.0
+        );
+
+        let ron = "(shader_parameters: (bool_enable_agent_bounce: 0))";
+        assert_eq!(
+            from_str(ron, Format::Ron)
+                .unwrap()
+                .shader_parameters
+                .boundary_mode,
+            BoundaryMode::Absorb
+        );
+
+        let both = "[shader_parameters]\nbool_enable_agent_bounce = 0\nboundary_mode = \"wrap\"\n";
+        assert!(boundary_mode(both).is_err());
+    }
+
+    #[test]
+    fn rejects_species_counts_the_shader_cannot_hold() {
+        let species = |n: u32| {
+            let toml = format!("[shader_parameters]\nnumber_of_species = {}\n", n);
+            from_str(&toml, Format::Toml).map(|p| p.shader_parameters.number_of_species)
+        };
+
+        assert_eq!(species(1).unwrap(), 1);
+        assert_eq!(species(MAX_SPECIES as u32).unwrap(), MAX_SPECIES as u32);
+        assert!(matches!(species(0), Err(Error::Parse(_))));
+        assert!(matches!(
+            species(MAX_SPECIES as u32 + 1),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn formats_follow_the_extension() {
+        assert_eq!(Format::from_path(Path::new("a.TOML")), Some(Format::Toml));
+        assert_eq!(Format::from_path(Path::new("a/b.json")), Some(Format::Json));
+        assert_eq!(Format::from_path(Path::new("c.ron")), Some(Format::Ron));
+        assert_eq!(Format::from_path(Path::new("c.yaml")), None);
+        assert!(matches!(load("c.yaml"), Err(Error::UnknownFormat(_))));
+    }
+}
@@ -0,0 +1,529 @@
+//! Keyboard bindings for changing the shader parameters while the viewer runs.
+//!
+//! Every flag can be toggled and every float increased or decreased, and either can be reset to
+//! its default. The bindings can be replaced from a file in any config format, e.g. in TOML:
+//!
+//! ```toml
+//! [[binding]]
+//! key = "KeyS"
+//! action = "increase"
+//! parameter = "sensor_distance"
+//! step = 0.25
+//!
+//! [[binding]]
+//! key = "KeyS"
+//! modifiers = ["shift"]
+//! action = "decrease"
+//! parameter = "sensor_distance"
+//!
+//! [[binding]]
+//! key = "KeyR"
+//! action = "randomize"
+//! ```
+//!
+//! Keys are named as in `winit::keyboard::KeyCode`.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use winit::keyboard::{KeyCode, ModifiersState};
+
+use crate::{
+    config::{self, Format},
+    parameters::ShaderParameters,
+};
+
+/// How much `increase` and `decrease` change a value when the binding gives no step.
+const DEFAULT_STEP: f32 = 0.1;
+
+/// What can be changed from the keyboard, named after the fields of `ShaderParameters`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    BoolEnableAgentDeposit,
+    BoolEnableAgentRotate,
+    BoolEnableAgentRotateLeft,
+    BoolEnableAgentRotateRandomly,
+    BoolEnableAgentRotateRight,
+    BoolEnableColor,
+    BoolEnableDecay,
+    BoolEnableDiffuse,
+    BoolEnableRenderTrailMap,
+    BoolEnableHighDensityDispersion,
+    AgentSpeed,
+    VertexStretch,
+    DecayStrength,
+    SensorAngleDegrees,
+    MaxTurnAngleDegrees,
+    MaxRandTurnAngleDegrees,
+    HighDensityThreshold,
+    HighDensitySpeedBoost,
+    DepositStrength,
+    SensorDistance,
+    FoodDepositStrength,
+}
+
+enum Field<'a> {
+    Flag(&'a mut u32),
+    Number(&'a mut f32),
+}
+
+impl Parameter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Parameter::BoolEnableAgentDeposit => "bool_enable_agent_deposit",
+            Parameter::BoolEnableAgentRotate => "bool_enable_agent_rotate",
+            Parameter::BoolEnableAgentRotateLeft => "bool_enable_agent_rotate_left",
+            Parameter::BoolEnableAgentRotateRandomly => "bool_enable_agent_rotate_randomly",
+            Parameter::BoolEnableAgentRotateRight => "bool_enable_agent_rotate_right",
+            Parameter::BoolEnableColor => "bool_enable_color",
+            Parameter::BoolEnableDecay => "bool_enable_decay",
+            Parameter::BoolEnableDiffuse => "bool_enable_diffuse",
+            Parameter::BoolEnableRenderTrailMap => "bool_enable_render_trail_map",
+            Parameter::BoolEnableHighDensityDispersion => "bool_enable_high_density_dispersion",
+            Parameter::AgentSpeed => "agent_speed",
+            Parameter::VertexStretch => "vertex_stretch",
+            Parameter::DecayStrength => "decay_strength",
+            Parameter::SensorAngleDegrees => "sensor_angle_degrees",
+            Parameter::MaxTurnAngleDegrees => "max_turn_angle_degrees",
+            Parameter::MaxRandTurnAngleDegrees => "max_rand_turn_angle_degrees",
+            Parameter::HighDensityThreshold => "high_density_threshold",
+            Parameter::HighDensitySpeedBoost => "high_density_speed_boost",
+            Parameter::DepositStrength => "deposit_strength",
+            Parameter::SensorDistance => "sensor_distance",
+            Parameter::FoodDepositStrength => "food_deposit_strength",
+        }
+    }
+
+    fn field(self, ctx: &mut ShaderParameters) -> Field<'_> {
+        match self {
+            Parameter::BoolEnableAgentDeposit => Field::Flag(&mut ctx.bool_enable_agent_deposit),
+            Parameter::BoolEnableAgentRotate => Field::Flag(&mut ctx.bool_enable_agent_rotate),
+            Parameter::BoolEnableAgentRotateLeft => {
+                Field::Flag(&mut ctx.bool_enable_agent_rotate_left)
+            }
+            Parameter::BoolEnableAgentRotateRandomly => {
+                Field::Flag(&mut ctx.bool_enable_agent_rotate_randomly)
+            }
+            Parameter::BoolEnableAgentRotateRight => {
+                Field::Flag(&mut ctx.bool_enable_agent_rotate_right)
+            }
+            Parameter::BoolEnableColor => Field::Flag(&mut ctx.bool_enable_color),
+            Parameter::BoolEnableDecay => Field::Flag(&mut ctx.bool_enable_decay),
+            Parameter::BoolEnableDiffuse => Field::Flag(&mut ctx.bool_enable_diffuse),
+            Parameter::BoolEnableRenderTrailMap => {
+                Field::Flag(&mut ctx.bool_enable_render_trail_map)
+            }
+            Parameter::BoolEnableHighDensityDispersion => {
+                Field::Flag(&mut ctx.bool_enable_high_density_dispersion)
+            }
+            Parameter::AgentSpeed => Field::Number(&mut ctx.agent_speed),
+            Parameter::VertexStretch => Field::Number(&mut ctx.vertex_stretch),
+            Parameter::DecayStrength => Field::Number(&mut ctx.decay_strength),
+            Parameter::SensorAngleDegrees => Field::Number(&mut ctx.sensor_angle_degrees),
+            Parameter::MaxTurnAngleDegrees => Field::Number(&mut ctx.max_turn_angle_degrees),
+            Parameter::MaxRandTurnAngleDegrees => {
+                Field::Number(&mut ctx.max_rand_turn_angle_degrees)
+            }
+            Parameter::HighDensityThreshold => Field::Number(&mut ctx.high_density_threshold),
+            Parameter::HighDensitySpeedBoost => Field::Number(&mut ctx.high_density_speed_boost),
+            Parameter::DepositStrength => Field::Number(&mut ctx.deposit_strength),
+            Parameter::SensorDistance => Field::Number(&mut ctx.sensor_distance),
+            Parameter::FoodDepositStrength => Field::Number(&mut ctx.food_deposit_strength),
+        }
+    }
+
+    /// The current value as a line of TOML, ready to paste into a config file.
+    pub fn describe(self, ctx: &ShaderParameters) -> String {
+        let mut ctx = *ctx;
+        match self.field(&mut ctx) {
+            Field::Flag(value) => format!("{} = {}", self.name(), value),
+            Field::Number(value) => format!("{} = {}", self.name(), value),
+        }
+    }
+}
+
+/// What a key does to the shader parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Switches a flag on or off.
+    Toggle { parameter: Parameter },
+    /// Scales a float up by `1 + step`. Zero is raised to `step` instead.
+    Increase { parameter: Parameter, step: f32 },
+    /// Scales a float down by `1 + step`, undoing `increase`.
+    Decrease { parameter: Parameter, step: f32 },
+    /// Returns a parameter to its default value.
+    Reset { parameter: Parameter },
+    /// Picks random values for the parameters that shape the network (see
+    /// `ShaderParameters::randomize`).
+    Randomize,
+}
+
+impl Action {
+    /// Toggling a float, or increasing or decreasing a flag, leaves it as it is.
+    pub fn apply(&self, ctx: &mut ShaderParameters) {
+        match *self {
+            Action::Toggle { parameter } => {
+                if let Field::Flag(value) = parameter.field(ctx) {
+                    *value = u32::from(*value == 0);
+                }
+            }
+            Action::Increase { parameter, step } => {
+                if let Field::Number(value) = parameter.field(ctx) {
+                    *value = if *value == 0.0 {
+                        step
+                    } else {
+                        *value * (1.0 + step)
+                    };
+                }
+            }
+            Action::Decrease { parameter, step } => {
+                if let Field::Number(value) = parameter.field(ctx) {
+                    *value /= 1.0 + step;
+                }
+            }
+            Action::Reset { parameter } => {
+                let mut defaults = ShaderParameters::default();
+                match (parameter.field(ctx), parameter.field(&mut defaults)) {
+                    (Field::Flag(value), Field::Flag(default)) => *value = *default,
+                    (Field::Number(value), Field::Number(default)) => *value = *default,
+                    _ => unreachable!("a parameter is always the same kind of field"),
+                }
+            }
+            Action::Randomize => ctx.randomize(),
+        }
+    }
+
+    /// The parameter the action changes, or `None` when it changes several.
+    pub fn parameter(&self) -> Option<Parameter> {
+        match *self {
+            Action::Toggle { parameter }
+            | Action::Increase { parameter, .. }
+            | Action::Decrease { parameter, .. }
+            | Action::Reset { parameter } => Some(parameter),
+            Action::Randomize => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "BindingEntry", into = "BindingEntry")]
+pub struct Binding {
+    pub key: KeyCode,
+    /// Modifiers that must be held, and no others.
+    pub modifiers: Vec<Modifier>,
+    pub action: Action,
+}
+
+/// A binding as it is written in a file, with the action spelled out in plain fields so every
+/// config format can hold it.
+#[derive(Serialize, Deserialize)]
+struct BindingEntry {
+    key: KeyCode,
+    #[serde(default)]
+    modifiers: Vec<Modifier>,
+    action: ActionKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parameter: Option<Parameter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    step: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ActionKind {
+    Toggle,
+    Increase,
+    Decrease,
+    Reset,
+    Randomize,
+}
+
+impl TryFrom<BindingEntry> for Binding {
+    type Error = String;
+
+    fn try_from(entry: BindingEntry) -> Result<Self, Self::Error> {
+        let parameter = || {
+            entry
+                .parameter
+                .ok_or_else(|| format!("binding of {:?} needs a parameter", entry.key))
+        };
+        let step = entry.step.unwrap_or(DEFAULT_STEP);
+        // `decrease` divides by `1 + step`, which has to stay above 1 to undo `increase`
+        if !(step > 0.0 && step.is_finite()) {
+            return Err(format!(
+                "binding of {:?} needs a positive step, got {}",
+                entry.key, step
+            ));
+        }
+
+        let action = match entry.action {
+            ActionKind::Toggle => Action::Toggle {
+                parameter: parameter()?,
+            },
+            ActionKind::Increase => Action::Increase {
+                parameter: parameter()?,
+                step,
+            },
+            ActionKind::Decrease => Action::Decrease {
+                parameter: parameter()?,
+                step,
+            },
+            ActionKind::Reset => Action::Reset {
+                parameter: parameter()?,
+            },
+            ActionKind::Randomize => Action::Randomize,
+        };
+
+        Ok(Self {
+            key: entry.key,
+            modifiers: entry.modifiers,
+            action,
+        })
+    }
+}
+
+impl From<Binding> for BindingEntry {
+    fn from(binding: Binding) -> Self {
+        let (action, step) = match binding.action {
+            Action::Toggle { .. } => (ActionKind::Toggle, None),
+            Action::Increase { step, .. } => (ActionKind::Increase, Some(step)),
+            Action::Decrease { step, .. } => (ActionKind::Decrease, Some(step)),
+            Action::Reset { .. } => (ActionKind::Reset, None),
+            Action::Randomize => (ActionKind::Randomize, None),
+        };
+
+        Self {
+            key: binding.key,
+            modifiers: binding.modifiers,
+            action,
+            parameter: binding.action.parameter(),
+            step,
+        }
+    }
+}
+
+impl Binding {
+    fn new(key: KeyCode, modifiers: &[Modifier], action: Action) -> Self {
+        Self {
+            key,
+            modifiers: modifiers.to_vec(),
+            action,
+        }
+    }
+
+    fn matches(&self, key: KeyCode, modifiers: ModifiersState) -> bool {
+        let held = |modifier| self.modifiers.contains(&modifier);
+
+        self.key == key
+            && held(Modifier::Shift) == modifiers.shift_key()
+            && held(Modifier::Ctrl) == modifiers.control_key()
+            && held(Modifier::Alt) == modifiers.alt_key()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    #[serde(rename = "binding", default)]
+    pub bindings: Vec<Binding>,
+}
+
+impl KeyBindings {
+    /// The action bound to `key` with exactly these modifiers held.
+    pub fn action(&self, key: KeyCode, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.matches(key, modifiers))
+            .map(|binding| binding.action)
+    }
+}
+
+/// A letter for every parameter: the letter alone toggles a flag or increases a float, with
+/// `Shift` it decreases the float and with `Ctrl` it resets either. `R` randomizes.
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let flags = [
+            (KeyCode::KeyP, Parameter::BoolEnableAgentDeposit),
+            (KeyCode::KeyY, Parameter::BoolEnableAgentRotate),
+            (KeyCode::KeyU, Parameter::BoolEnableAgentRotateLeft),
+            (KeyCode::KeyI, Parameter::BoolEnableAgentRotateRandomly),
+            (KeyCode::KeyO, Parameter::BoolEnableAgentRotateRight),
+            (KeyCode::KeyK, Parameter::BoolEnableColor),
+            (KeyCode::KeyE, Parameter::BoolEnableDecay),
+            (KeyCode::KeyD, Parameter::BoolEnableDiffuse),
+            (KeyCode::KeyT, Parameter::BoolEnableRenderTrailMap),
+            (KeyCode::KeyH, Parameter::BoolEnableHighDensityDispersion),
+        ];
+        let numbers = [
+            (KeyCode::KeyV, Parameter::AgentSpeed),
+            (KeyCode::KeyZ, Parameter::VertexStretch),
+            (KeyCode::KeyX, Parameter::DecayStrength),
+            (KeyCode::KeyA, Parameter::SensorAngleDegrees),
+            (KeyCode::KeyM, Parameter::MaxTurnAngleDegrees),
+            (KeyCode::KeyN, Parameter::MaxRandTurnAngleDegrees),
+            (KeyCode::KeyQ, Parameter::HighDensityThreshold),
+            (KeyCode::KeyW, Parameter::HighDensitySpeedBoost),
+            (KeyCode::KeyG, Parameter::DepositStrength),
+            (KeyCode::KeyS, Parameter::SensorDistance),
+            (KeyCode::KeyF, Parameter::FoodDepositStrength),
+        ];
+
+        let mut bindings = vec![Binding::new(KeyCode::KeyR, &[], Action::Randomize)];
+        for (key, parameter) in flags {
+            bindings.push(Binding::new(key, &[], Action::Toggle { parameter }));
+            bindings.push(Binding::new(
+                key,
+                &[Modifier::Ctrl],
+                Action::Reset { parameter },
+            ));
+        }
+        for (key, parameter) in numbers {
+            let step = DEFAULT_STEP;
+            bindings.push(Binding::new(key, &[], Action::Increase { parameter, step }));
+            bindings.push(Binding::new(
+                key,
+                &[Modifier::Shift],
+                Action::Decrease { parameter, step },
+            ));
+            bindings.push(Binding::new(
+                key,
+                &[Modifier::Ctrl],
+                Action::Reset { parameter },
+            ));
+        }
+
+        Self { bindings }
+    }
+}
+
+/// Reads bindings from a config file, picking the format from its extension like `config::load`.
+pub fn load(path: impl AsRef<Path>) -> Result<KeyBindings, config::Error> {
+    let path = path.as_ref();
+    let format = Format::from_path(path)
+        .ok_or_else(|| config::Error::UnknownFormat(path.display().to_string()))?;
+    let contents = fs::read_to_string(path)?;
+
+    let parse_error = |e: &dyn std::fmt::Display| config::Error::Parse(e.to_string());
+    match format {
+        Format::Toml => toml::from_str(&contents).map_err(|e| parse_error(&e)),
+        Format::Json => serde_json::from_str(&contents).map_err(|e| parse_error(&e)),
+        Format::Ron => ron::from_str(&contents).map_err(|e| parse_error(&e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(toml: &str) -> Result<Binding, String> {
+        toml::from_str(toml).map_err(|e| e.message().to_string())
+    }
+
+    #[test]
+    fn bindings_need_a_parameter_and_a_positive_step() {
+        assert_eq!(
+            binding("key = \"KeyS\"\naction = \"decrease\"\nparameter = \"sensor_distance\""),
+            Ok(Binding::new(
+                KeyCode::KeyS,
+                &[],
+                Action::Decrease {
+                    parameter: Parameter::SensorDistance,
+                    step: DEFAULT_STEP
+                }
+            ))
+        );
+        assert_eq!(
+            binding("key = \"KeyR\"\naction = \"randomize\"")
+                .unwrap()
+                .action,
+            Action::Randomize
+        );
+
+        let missing = binding("key = \"KeyS\"\naction = \"increase\"").unwrap_err();
+        assert!(missing.contains("needs a parameter"), "{}", missing);
+
+        for step in ["0.0", "-0.5", "-1.0", "-2.0", "inf", "nan"] {
+            let toml = format!(
+                "key = \"KeyS\"\naction = \"decrease\"\nparameter = \"sensor_distance\"\nstep = {}",
+                step
+            );
+            let error = binding(&toml).unwrap_err();
+            assert!(error.contains("positive step"), "{}: {}", step, error);
+        }
+    }
+
+    #[test]
+    fn default_bindings_round_trip() {
+        let bindings = KeyBindings::default();
+        let toml = toml::to_string(&bindings).unwrap();
+        assert_eq!(toml::from_str::<KeyBindings>(&toml).unwrap(), bindings);
+    }
+
+    #[test]
+    fn decrease_undoes_increase() {
+        let parameter = Parameter::SensorDistance;
+        let mut ctx = ShaderParameters::default();
+        let original = ctx.sensor_distance;
+
+        Action::Increase {
+            parameter,
+            step: 0.25,
+        }
+        .apply(&mut ctx);
+        assert_eq!(ctx.sensor_distance, original * 1.25);
+        Action::Decrease {
+            parameter,
+            step: 0.25,
+        }
+        .apply(&mut ctx);
+        assert!((ctx.sensor_distance - original).abs() <= original * f32::EPSILON);
+
+        // Zero is raised to the step rather than scaled
+        ctx.sensor_distance = 0.0;
+        Action::Increase {
+            parameter,
+            step: 0.25,
+        }
+        .apply(&mut ctx);
+        assert_eq!(ctx.sensor_distance, 0.25);
+
+        // Flags are left alone
+        let flags = ctx.bool_enable_decay;
+        Action::Increase {
+            parameter: Parameter::BoolEnableDecay,
+            step: 0.25,
+        }
+        .apply(&mut ctx);
+        assert_eq!(ctx.bool_enable_decay, flags);
+    }
+
+    #[test]
+    fn reset_returns_to_the_default() {
+        let defaults = ShaderParameters::default();
+        let mut ctx = defaults;
+        ctx.decay_strength *= 3.0;
+        ctx.bool_enable_diffuse = u32::from(defaults.bool_enable_diffuse == 0);
+        ctx.agent_speed = 7.0;
+
+        Action::Reset {
+            parameter: Parameter::DecayStrength,
+        }
+        .apply(&mut ctx);
+        Action::Reset {
+            parameter: Parameter::BoolEnableDiffuse,
+        }
+        .apply(&mut ctx);
+
+        assert_eq!(ctx.decay_strength, defaults.decay_strength);
+        assert_eq!(ctx.bool_enable_diffuse, defaults.bool_enable_diffuse);
+        assert_eq!(ctx.agent_speed, 7.0);
+    }
+}
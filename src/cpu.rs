@@ -0,0 +1,695 @@
+//! Pure-Rust port of the compute kernels in `shader.wgsl`.
+//!
+//! Works on the same `Agent` and trail map layouts and reads the same `ShaderParameters`, so it can
+//! run where no GPU is available and serve as a reference for the shader's output. Keep the
+//! functions here in step with their WGSL namesakes.
+
+use rayon::prelude::*;
+
+use crate::{
+    agent::{initial_agent_distribution, Agent},
+    backend::Backend,
+    checkpoint::Checkpoint,
+    environment::{self, Environment},
+    parameters::{
+        BoundaryMode, DiffusionEdgeMode, DiffusionKernel, Parameters, ShaderParameters,
+        SpeciesContext,
+    },
+};
+
+pub struct CpuSimulation {
+    pub params: Parameters,
+    pub environment: Environment,
+    pub agents: Vec<Agent>,
+    pub trail_map: Vec<f32>,
+}
+
+impl CpuSimulation {
+    /// Fails if the environment (e.g. a food image) cannot be loaded.
+    pub fn new(mut params: Parameters) -> Result<Self, environment::Error> {
+        params.prepare();
+
+        let environment = Environment::new(&params)?;
+        let agents = initial_agent_distribution(&params);
+
+        // Start with a black canvas
+        let trail_map = vec![0.0; params.shader_parameters.trail_map_len()];
+
+        Ok(Self {
+            params,
+            environment,
+            agents,
+            trail_map,
+        })
+    }
+
+    /// Picks up a run from where the checkpoint was taken.
+    pub fn from_checkpoint(checkpoint: Checkpoint) -> Result<Self, environment::Error> {
+        // The environment is static, so it is rebuilt rather than stored in the checkpoint
+        let environment = Environment::new(&checkpoint.params)?;
+
+        Ok(Self {
+            params: checkpoint.params,
+            environment,
+            agents: checkpoint.agents,
+            trail_map: checkpoint.trail_map,
+        })
+    }
+
+    /// Advances the simulation by one tick, in the same order as `Simulation::step`.
+    pub fn step(&mut self) {
+        self.diffuse_and_decay();
+        self.agent_sense_move_deposit();
+
+        self.params.tick = self.params.tick.wrapping_add(1);
+    }
+
+    pub fn diffuse_and_decay(&mut self) {
+        let ctx = &self.params.shader_parameters;
+        let width = ctx.canvas_width as usize;
+        let height = ctx.canvas_height as usize;
+        let layer_len = width * height;
+        let environment = &self.environment;
+        let kernel = Kernel::new(&self.params.diffusion_kernel);
+
+        // Every pixel reads the previous state of its neighbourhood
+        let previous = self.trail_map.clone();
+        let previous_layers: Vec<&[f32]> = previous.chunks(layer_len).collect();
+
+        // A separable kernel first blurs the rows, which diffusion then continues from
+        let diffused_rows = (kernel.separable && ctx.bool_enable_diffuse != 0).then(|| {
+            let mut diffused_rows = vec![0.0; previous.len()];
+            diffused_rows
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(|(row_idx, row)| {
+                    let layer = previous_layers[row_idx / height];
+                    let y = row_idx % height;
+
+                    for (x, value) in row.iter_mut().enumerate() {
+                        let taps = (-kernel.radius..=kernel.radius)
+                            .zip(&kernel.weights)
+                            .map(|(i, &weight)| ([i, 0], weight));
+                        *value = convolve(ctx, layer, &environment.mask, x as u32, y as u32, taps);
+                    }
+                });
+            diffused_rows
+        });
+        let diffused_rows_layers: Option<Vec<&[f32]>> = diffused_rows
+            .as_ref()
+            .map(|diffused_rows| diffused_rows.chunks(layer_len).collect());
+
+        // Every species' trail spreads and fades on its own
+        self.trail_map
+            .par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(row_idx, row)| {
+                let layer = row_idx / height;
+                let y = row_idx % height;
+                let previous = previous_layers[layer];
+                let diffused_rows = diffused_rows_layers.as_ref().map(|layers| layers[layer]);
+
+                for (x, value) in row.iter_mut().enumerate() {
+                    *value = diffuse_and_decay_pixel(
+                        ctx,
+                        &kernel,
+                        previous,
+                        diffused_rows,
+                        environment,
+                        x as u32,
+                        y as u32,
+                    );
+                }
+            });
+    }
+
+    pub fn agent_sense_move_deposit(&mut self) {
+        let ctx = &self.params.shader_parameters;
+        let species_table = self.params.species_table();
+        let trail_map = &self.trail_map;
+        let mask = &self.environment.mask;
+
+        let number_of_active_agents = (ctx.number_of_active_agents as usize).min(self.agents.len());
+        let active_agents = &mut self.agents[..number_of_active_agents];
+
+        // SENSE, ROTATE and MOVE only read the trail map, so all agents can do that in parallel
+        active_agents.par_iter_mut().for_each(|agent| {
+            *agent = sense_and_move(ctx, &species_table, trail_map, mask, *agent)
+        });
+
+        // DEPOSIT: Count the agents on every pixel like the shader, and add what they deposit to the
+        // trail map once all agents are done. Only agents of the layer's species deposit on it.
+        let mut deposits = vec![0u32; self.trail_map.len()];
+        if ctx.bool_enable_agent_deposit != 0 {
+            for agent in active_agents.iter() {
+                if let Some(pixel_idx) = pixel_index(ctx, agent.position) {
+                    if mask[pixel_idx] != 0 {
+                        continue;
+                    }
+
+                    deposits[layer_offset(ctx, agent.species) + pixel_idx] += 1;
+                }
+            }
+        }
+
+        let layer_len = layer_offset(ctx, 1);
+        self.trail_map
+            .par_chunks_mut(layer_len)
+            .zip(deposits.par_chunks(layer_len))
+            .zip(&species_table)
+            .for_each(|((layer, deposits), species)| {
+                for (value, &deposit) in layer.iter_mut().zip(deposits) {
+                    let deposit = deposit as f32 * species.params.deposit_strength;
+                    *value = (*value + deposit).clamp(0.0, 1.0);
+                }
+            });
+    }
+}
+
+impl Backend for CpuSimulation {
+    fn params(&self) -> &Parameters {
+        &self.params
+    }
+
+    fn step(&mut self) {
+        CpuSimulation::step(self);
+    }
+
+    fn agents(&self) -> Vec<Agent> {
+        self.agents.clone()
+    }
+
+    fn trail_map(&self) -> Vec<f32> {
+        self.trail_map.clone()
+    }
+}
+
+/// Diffusion kernel laid out as the shader reads it.
+struct Kernel {
+    radius: i32,
+    separable: bool,
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    fn new(kernel: &DiffusionKernel) -> Self {
+        Self {
+            radius: kernel.radius() as i32,
+            separable: kernel.is_separable(),
+            weights: kernel.weights(),
+        }
+    }
+}
+
+/// Diffuses, decays and feeds a pixel. With a separable kernel, diffusion continues from
+/// `diffused_rows`.
+fn diffuse_and_decay_pixel(
+    ctx: &ShaderParameters,
+    kernel: &Kernel,
+    trail_map: &[f32],
+    diffused_rows: Option<&[f32]>,
+    environment: &Environment,
+    x: u32,
+    y: u32,
+) -> f32 {
+    let idx = (y * ctx.canvas_width + x) as usize;
+    let mask = &environment.mask;
+
+    // Walls hold no trail
+    if mask[idx] != 0 {
+        return 0.0;
+    }
+
+    let mut value = trail_map[idx];
+
+    // DIFFUSE
+    if ctx.bool_enable_diffuse != 0 {
+        let radius = kernel.radius;
+        value = match diffused_rows {
+            // Second pass: blur the column of the pixel in the blurred rows
+            Some(diffused_rows) => {
+                let taps = (-radius..=radius)
+                    .zip(&kernel.weights)
+                    .map(|(j, &weight)| ([0, j], weight));
+                convolve(ctx, diffused_rows, mask, x, y, taps)
+            }
+            None => {
+                let taps = (-radius..=radius)
+                    .flat_map(|j| (-radius..=radius).map(move |i| [i, j]))
+                    .zip(&kernel.weights)
+                    .map(|(offset, &weight)| (offset, weight));
+                convolve(ctx, trail_map, mask, x, y, taps)
+            }
+        };
+    }
+
+    // DECAY
+    if ctx.bool_enable_decay != 0 {
+        value *= 1.0 - ctx.decay_strength;
+    }
+
+    // FEED: Food keeps stimulating the trail around it
+    (value + environment.food[idx] * ctx.food_deposit_strength).clamp(0.0, 1.0)
+}
+
+/// Weighted sum of the neighbours `taps` lists by their offset from `(x, y)`, in the order the
+/// shader adds them up.
+fn convolve(
+    ctx: &ShaderParameters,
+    layer: &[f32],
+    mask: &[u32],
+    x: u32,
+    y: u32,
+    taps: impl Iterator<Item = ([i32; 2], f32)>,
+) -> f32 {
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut used = 0.0;
+    for ([i, j], weight) in taps {
+        total += weight;
+
+        if let Some(neighbour_idx) = diffusion_neighbour(ctx, mask, x, y, i, j) {
+            sum += weight * layer[neighbour_idx];
+            used += weight;
+        }
+    }
+
+    // Renormalizing makes up for the neighbours left out
+    if ctx.diffusion_edge_mode == DiffusionEdgeMode::Renormalize && used != 0.0 {
+        return sum * total / used;
+    }
+    sum
+}
+
+/// Index within a layer of the pixel diffusion takes for the neighbour `(i, j)` away from
+/// `(x, y)`, or `None` when there is none: outside the canvas, trail is empty, and it does not
+/// spread through walls.
+fn diffusion_neighbour(
+    ctx: &ShaderParameters,
+    mask: &[u32],
+    x: u32,
+    y: u32,
+    i: i32,
+    j: i32,
+) -> Option<usize> {
+    let xi = edge_neighbour(ctx, x as i32 + i, ctx.canvas_width as i32);
+    let yi = edge_neighbour(ctx, y as i32 + j, ctx.canvas_height as i32);
+
+    if xi < 0 || xi > ctx.canvas_width as i32 - 1 || yi < 0 || yi > ctx.canvas_height as i32 - 1 {
+        return None;
+    }
+
+    let neighbour_idx = (yi as u32 * ctx.canvas_width + xi as u32) as usize;
+    (mask[neighbour_idx] == 0).then_some(neighbour_idx)
+}
+
+/// Coordinate of the pixel diffusion takes for the neighbour at `i`, which may lie beyond the edges
+/// of a canvas `size` pixels across.
+fn edge_neighbour(ctx: &ShaderParameters, i: i32, size: i32) -> i32 {
+    match ctx.diffusion_edge_mode {
+        DiffusionEdgeMode::Zero | DiffusionEdgeMode::Renormalize => i,
+        DiffusionEdgeMode::Clamp => i.clamp(0, size - 1),
+        DiffusionEdgeMode::Wrap => i.rem_euclid(size),
+        DiffusionEdgeMode::Mirror if i < 0 => (-i).clamp(0, size - 1),
+        DiffusionEdgeMode::Mirror if i > size - 1 => (2 * (size - 1) - i).clamp(0, size - 1),
+        DiffusionEdgeMode::Mirror => i,
+    }
+}
+
+fn sense_and_move(
+    ctx: &ShaderParameters,
+    species_table: &[SpeciesContext],
+    trail_map: &[f32],
+    mask: &[u32],
+    mut agent: Agent,
+) -> Agent {
+    // Absorbed agents stay where they left the canvas
+    if ctx.boundary_mode == BoundaryMode::Absorb && is_out_of_bounds(ctx, agent.position) {
+        return agent;
+    }
+
+    let species = &species_table[agent.species as usize];
+
+    // SENSE: Get deposit values at the sensors
+    let sensor_ahead_val = sense(ctx, species, trail_map, &agent, 0.0);
+    let sensor_ccw_val = sense(
+        ctx,
+        species,
+        trail_map,
+        &agent,
+        species.params.sensor_angle_degrees,
+    );
+    let sensor_cw_val = sense(
+        ctx,
+        species,
+        trail_map,
+        &agent,
+        -species.params.sensor_angle_degrees,
+    );
+
+    // Every agent draws from its own random stream
+    let rng_state = &mut agent.rng_state;
+
+    // ROTATE: Update agent direction (rotate its velocity)
+    if ctx.bool_enable_agent_rotate != 0 {
+        if ctx.bool_enable_agent_rotate_left != 0
+            && sensor_ccw_val > sensor_ahead_val
+            && sensor_ahead_val > sensor_cw_val
+        {
+            agent.velocity = rotate_ccw(species.params.max_turn_angle_degrees, agent.velocity);
+        }
+        if ctx.bool_enable_agent_rotate_right != 0
+            && sensor_cw_val > sensor_ahead_val
+            && sensor_ahead_val > sensor_ccw_val
+        {
+            agent.velocity = rotate_cw(species.params.max_turn_angle_degrees, agent.velocity);
+        }
+        if ctx.bool_enable_agent_rotate_right != 0
+            && sensor_cw_val > sensor_ahead_val
+            && sensor_ccw_val > sensor_ahead_val
+        {
+            let ra = rand_sign(rng_state) * species.params.max_turn_angle_degrees;
+            agent.velocity = rotate_cw(ra, agent.velocity);
+        }
+    }
+
+    let mut speed = species.params.agent_speed;
+
+    // TWIST: If the deposit density is too great (too many agents in the same spot)
+    if ctx.bool_enable_high_density_dispersion != 0 {
+        let density = deposit_strength_at(ctx, trail_map, agent.species, agent.position);
+        if density >= ctx.high_density_threshold {
+            speed += ctx.high_density_speed_boost * density;
+
+            agent.velocity = rotate_cw(
+                random_float_in_range(
+                    -species.params.max_rand_turn_angle_degrees,
+                    species.params.max_rand_turn_angle_degrees,
+                    rng_state,
+                ),
+                agent.velocity,
+            );
+        }
+    }
+
+    // MOVE: Move agent forward, unless that runs it into a wall
+    let step = [agent.velocity[0] * speed, agent.velocity[1] * speed];
+    let [x, y] = agent.position;
+    if is_blocked(ctx, mask, [x + step[0], y + step[1]]) && !is_blocked(ctx, mask, [x, y]) {
+        // Bounce off the wall along the axes that run into it, or turn back in a corner
+        let blocked_x = is_blocked(ctx, mask, [x + step[0], y]);
+        let blocked_y = is_blocked(ctx, mask, [x, y + step[1]]);
+        if blocked_x {
+            agent.velocity[0] = -agent.velocity[0];
+        }
+        if blocked_y {
+            agent.velocity[1] = -agent.velocity[1];
+        }
+        if !blocked_x && !blocked_y {
+            agent.velocity = [-agent.velocity[0], -agent.velocity[1]];
+        }
+    } else {
+        agent.position = [x + step[0], y + step[1]];
+    }
+
+    // BOUNDARY: Handle agents that left the canvas
+    if is_out_of_bounds(ctx, agent.position) {
+        match ctx.boundary_mode {
+            BoundaryMode::Wrap => {
+                agent.position = [
+                    wrap(agent.position[0], ctx.canvas_width as f32),
+                    wrap(agent.position[1], ctx.canvas_height as f32),
+                ];
+            }
+            BoundaryMode::Reflect => agent = reflect_off_edges(ctx, agent),
+            BoundaryMode::Absorb => {}
+            BoundaryMode::RespawnRandom => {
+                agent.position = [
+                    random_float_in_range(0.0, (ctx.canvas_width - 1) as f32, rng_state),
+                    random_float_in_range(0.0, (ctx.canvas_height - 1) as f32, rng_state),
+                ];
+                agent.velocity = random_heading(rng_state);
+            }
+            BoundaryMode::RespawnInitial => {
+                agent.position = agent.initial_position;
+                agent.velocity = random_heading(rng_state);
+            }
+        }
+    }
+
+    agent
+}
+
+/// Mirrors the position back onto the canvas and turns the agent away from the edges it crossed.
+fn reflect_off_edges(ctx: &ShaderParameters, mut agent: Agent) -> Agent {
+    let max_position = [
+        (ctx.canvas_width - 1) as f32,
+        (ctx.canvas_height - 1) as f32,
+    ];
+
+    for (axis, max) in max_position.into_iter().enumerate() {
+        let position = &mut agent.position[axis];
+        let velocity = &mut agent.velocity[axis];
+
+        if *position < 0.0 {
+            *position = -*position;
+            *velocity = velocity.abs();
+        } else if *position > max {
+            *position = 2.0 * max - *position;
+            *velocity = -velocity.abs();
+        }
+
+        // Agents far off the canvas, e.g. placed there initially, come back at the edge
+        *position = position.clamp(0.0, max);
+    }
+
+    agent
+}
+
+/// Wraps a coordinate into `[0, size)`.
+fn wrap(v: f32, size: f32) -> f32 {
+    v - size * (v / size).floor()
+}
+
+fn random_heading(rng_state: &mut u32) -> [f32; 2] {
+    rotate_cw(random_float_in_range(0.0, 360.0, rng_state), [1.0, 0.0])
+}
+
+// Rotate clockwise, assuming a screen space coordinate system (y pointing down).
+fn rotate_cw(degrees: f32, v: [f32; 2]) -> [f32; 2] {
+    let a = degrees.to_radians();
+    [
+        v[0] * a.cos() - v[1] * a.sin(),
+        v[0] * a.sin() + v[1] * a.cos(),
+    ]
+}
+
+fn rotate_ccw(degrees: f32, v: [f32; 2]) -> [f32; 2] {
+    rotate_cw(-degrees, v)
+}
+
+fn sense(
+    ctx: &ShaderParameters,
+    species: &SpeciesContext,
+    trail_map: &[f32],
+    agent: &Agent,
+    sensor_angle_ccw_degrees: f32,
+) -> f32 {
+    let length = (agent.velocity[0].powi(2) + agent.velocity[1].powi(2)).sqrt();
+    let facing_direction = [agent.velocity[0] / length, agent.velocity[1] / length];
+    let rotated_facing_direction = rotate_ccw(sensor_angle_ccw_degrees, facing_direction);
+    let sensor = [
+        agent.position[0] + rotated_facing_direction[0] * species.params.sensor_distance,
+        agent.position[1] + rotated_facing_direction[1] * species.params.sensor_distance,
+    ];
+
+    // Weigh the trail of every species by how much the agent's species is drawn to it
+    (0..ctx.number_of_species)
+        .map(|layer| {
+            species.attraction[layer as usize] * deposit_strength_at(ctx, trail_map, layer, sensor)
+        })
+        .sum()
+}
+
+fn deposit_strength_at(
+    ctx: &ShaderParameters,
+    trail_map: &[f32],
+    layer: u32,
+    pos: [f32; 2],
+) -> f32 {
+    pixel_index(ctx, pos).map_or(0.0, |idx| trail_map[layer_offset(ctx, layer) + idx])
+}
+
+/// Start of the trail layer of a species in the trail map.
+fn layer_offset(ctx: &ShaderParameters, layer: u32) -> usize {
+    (layer * ctx.canvas_width * ctx.canvas_height) as usize
+}
+
+/// Index of the pixel nearest to `pos` within a layer, or `None` when `pos` is off the canvas. On a
+/// toroidal canvas, positions off one edge continue on the opposite one.
+fn pixel_index(ctx: &ShaderParameters, pos: [f32; 2]) -> Option<usize> {
+    if ctx.boundary_mode == BoundaryMode::Wrap {
+        // Positions just short of the far edge round up onto it, which is the near edge again
+        let pixel_x =
+            wrap(pos[0], ctx.canvas_width as f32).round_ties_even() as u32 % ctx.canvas_width;
+        let pixel_y =
+            wrap(pos[1], ctx.canvas_height as f32).round_ties_even() as u32 % ctx.canvas_height;
+        return Some((pixel_y * ctx.canvas_width + pixel_x) as usize);
+    }
+
+    if is_out_of_bounds(ctx, pos) {
+        return None;
+    }
+
+    // WGSL's `round` rounds half to even
+    let pixel_x = pos[0].round_ties_even() as u32;
+    let pixel_y = pos[1].round_ties_even() as u32;
+    Some((pixel_y * ctx.canvas_width + pixel_x) as usize)
+}
+
+/// Whether an obstacle covers the pixel at `pos`. Off the canvas is left to the borders.
+fn is_blocked(ctx: &ShaderParameters, mask: &[u32], pos: [f32; 2]) -> bool {
+    pixel_index(ctx, pos).is_some_and(|idx| mask[idx] != 0)
+}
+
+fn is_out_of_bounds(ctx: &ShaderParameters, pos: [f32; 2]) -> bool {
+    pos[0] < 0.0
+        || pos[0] > (ctx.canvas_width - 1) as f32
+        || pos[1] < 0.0
+        || pos[1] > (ctx.canvas_height - 1) as f32
+}
+
+fn rand_sign(rng_state: &mut u32) -> f32 {
+    // WGSL's `sign` returns zero for zero
+    let value = random_float_in_range(-1.0, 1.0, rng_state);
+    if value == 0.0 {
+        0.0
+    } else {
+        value.signum()
+    }
+}
+
+fn random_float_in_range(min: f32, max: f32, rng_state: &mut u32) -> f32 {
+    let random_float = rand_u32(rng_state) as f32 / u32::MAX as f32;
+    min + random_float * (max - min)
+}
+
+fn rand_u32(rng_state: &mut u32) -> u32 {
+    let state = *rng_state;
+    *rng_state = state.wrapping_mul(747796405).wrapping_add(2891336453);
+    let h = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (h >> 22) ^ h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parameters::InitialConditions;
+
+    fn small_params(width: u32, height: u32, number_of_agents: u32) -> Parameters {
+        Parameters::builder()
+            .number_of_agents(number_of_agents)
+            .seed(Some(7))
+            .initial_conditions(InitialConditions {
+                initial_circle_radius: 10.0,
+                ..Default::default()
+            })
+            .shader_parameters(ShaderParameters {
+                canvas_width: width,
+                canvas_height: height,
+                ..Default::default()
+            })
+            .build()
+    }
+
+    fn resting_agent(position: [f32; 2]) -> Agent {
+        Agent {
+            position,
+            velocity: [1.0, 0.0],
+            initial_position: position,
+            rng_state: 0,
+            species: 0,
+        }
+    }
+
+    #[test]
+    fn fixed_seed_steps_deterministically() {
+        let run = || {
+            let mut simulation = CpuSimulation::new(small_params(48, 32, 500)).unwrap();
+            for _ in 0..10 {
+                simulation.step();
+            }
+            simulation
+        };
+        let (a, b) = (run(), run());
+
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&a.agents),
+            bytemuck::cast_slice::<Agent, u8>(&b.agents)
+        );
+        assert_eq!(a.trail_map, b.trail_map);
+        assert!(a.trail_map.iter().any(|&value| value > 0.0));
+        assert_eq!(a.params.tick, 10);
+    }
+
+    #[test]
+    fn deposits_add_up_per_pixel() {
+        let mut simulation = CpuSimulation::new(small_params(8, 8, 3)).unwrap();
+        let ctx = &mut simulation.params.shader_parameters;
+        ctx.agent_speed = 0.0;
+        ctx.bool_enable_agent_rotate = 0;
+        ctx.deposit_strength = 0.1;
+        simulation.agents = vec![resting_agent([3.0, 4.0]); 3];
+
+        simulation.agent_sense_move_deposit();
+
+        for (idx, &value) in simulation.trail_map.iter().enumerate() {
+            if idx == 4 * 8 + 3 {
+                assert_eq!(value, 3.0 * 0.1);
+            } else {
+                assert_eq!(value, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn deposits_do_not_overflow_on_crowded_pixels() {
+        let mut simulation = CpuSimulation::new(small_params(2, 2, 100_000)).unwrap();
+        let ctx = &mut simulation.params.shader_parameters;
+        ctx.agent_speed = 0.0;
+        ctx.bool_enable_agent_rotate = 0;
+        ctx.deposit_strength = 1.0;
+        simulation.agents = vec![resting_agent([1.0, 1.0]); 100_000];
+
+        simulation.agent_sense_move_deposit();
+
+        assert_eq!(simulation.trail_map, [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn diffusion_and_decay_spread_a_single_pixel() {
+        let mut simulation = CpuSimulation::new(small_params(3, 3, 0)).unwrap();
+        simulation.params.diffusion_kernel = DiffusionKernel::Box { radius: 1 };
+        let ctx = &mut simulation.params.shader_parameters;
+        ctx.diffusion_edge_mode = DiffusionEdgeMode::Zero;
+        ctx.decay_strength = 0.5;
+        simulation.trail_map[4] = 0.9;
+
+        simulation.diffuse_and_decay();
+
+        // Every pixel has the middle one in its 3x3 neighbourhood
+        let expected = 0.9 / 9.0 * 0.5;
+        for &value in &simulation.trail_map {
+            assert!((value - expected).abs() < 1e-7, "{} != {}", value, expected);
+        }
+
+        // Only the middle pixel sees all the others, the corners see a quarter of the canvas
+        simulation.trail_map = vec![0.9; 9];
+        simulation.params.shader_parameters.decay_strength = 0.0;
+        simulation.diffuse_and_decay();
+
+        assert!((simulation.trail_map[4] - 0.9).abs() < 1e-6);
+        assert!((simulation.trail_map[0] - 0.9 * 4.0 / 9.0).abs() < 1e-6);
+        assert!((simulation.trail_map[1] - 0.9 * 6.0 / 9.0).abs() < 1e-6);
+    }
+}
@@ -1,3 +1,7 @@
+/// Storage buffers bound at once in the compute stage: 2 in the shader context, 1 for the agents
+/// and 6 for the trail layer (see `resources`), one more than `wgpu::Limits::default()` allows.
+const STORAGE_BUFFERS_PER_SHADER_STAGE: u32 = 9;
+
 // Handle to the physical graphics and/or compute device.
 pub struct Device {
     pub adapter: wgpu::Adapter,
@@ -6,6 +10,16 @@ pub struct Device {
 }
 
 impl Device {
+    /// Picks an adapter without regard to any surface, for running without a window.
+    pub async fn headless() -> Self {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        Self::new(&instance, None).await
+    }
+
     pub async fn new(instance: &wgpu::Instance, surface: Option<&wgpu::Surface<'_>>) -> Self {
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -16,12 +30,26 @@ impl Device {
             .await
             .unwrap();
 
+        let limits = adapter.limits();
+        assert!(
+            limits.max_storage_buffers_per_shader_stage >= STORAGE_BUFFERS_PER_SHADER_STAGE,
+            "the graphics adapter supports {} storage buffers per shader stage, but {} are needed",
+            limits.max_storage_buffers_per_shader_stage,
+            STORAGE_BUFFERS_PER_SHADER_STAGE
+        );
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
                     required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
+                    // Large canvases need trail maps beyond the default buffer sizes
+                    required_limits: wgpu::Limits {
+                        max_buffer_size: limits.max_buffer_size,
+                        max_storage_buffer_binding_size: limits.max_storage_buffer_binding_size,
+                        max_storage_buffers_per_shader_stage: STORAGE_BUFFERS_PER_SHADER_STAGE,
+                        ..wgpu::Limits::default()
+                    },
                 },
                 None,
             )
@@ -0,0 +1,422 @@
+//! Static layers the agents move through, built once from the initial conditions.
+//!
+//! Food keeps stimulating the trail map around it every tick, so agents find their way to it and
+//! connect the sources into a transport network. Obstacles mask cells agents cannot enter and
+//! trail cannot diffuse into, to lay out mazes and arenas.
+
+use std::{fmt, fs, io, ops::RangeInclusive, path::Path};
+
+use image::imageops::FilterType;
+
+use crate::parameters::{FoodSource, Obstacle, Parameters};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Image(String),
+    Csv {
+        path: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Image(e) => write!(f, "cannot read image: {}", e),
+            Error::Csv {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path, line, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Image(e.to_string())
+    }
+}
+
+pub struct Environment {
+    /// Amount of food on every pixel of the canvas, between 0 and 1, row by row.
+    pub food: Vec<f32>,
+    /// 1 for every pixel of the canvas covered by an obstacle, 0 elsewhere, row by row.
+    pub mask: Vec<u32>,
+}
+
+impl Environment {
+    /// Rasterizes the food sources and obstacles of the initial conditions onto the canvas,
+    /// reading any files they refer to.
+    pub fn new(params: &Parameters) -> Result<Self, Error> {
+        let ctx = &params.shader_parameters;
+
+        let mut food = Layer::new(ctx.canvas_width, ctx.canvas_height);
+        for source in &params.initial_conditions.food {
+            match source {
+                FoodSource::Point { x, y } => food.fill_disc(*x, *y, 0.0),
+                FoodSource::Disc { x, y, radius } => food.fill_disc(*x, *y, *radius),
+                FoodSource::Image { path } => {
+                    food.add_image(path, FilterType::Triangle, |luma| luma)?
+                }
+                FoodSource::Csv { path, radius } => {
+                    for [x, y, radius] in read_csv(path, *radius)? {
+                        food.fill_disc(x, y, radius);
+                    }
+                }
+            }
+        }
+
+        let mut mask = Layer::new(ctx.canvas_width, ctx.canvas_height);
+        for obstacle in &params.initial_conditions.obstacles {
+            match obstacle {
+                Obstacle::Rectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                } => mask.fill_rectangle(*x, *y, *width, *height),
+                Obstacle::Circle { x, y, radius } => mask.fill_disc(*x, *y, *radius),
+                Obstacle::Polygon { points } => mask.fill_polygon(points),
+                // Walls stay crisp instead of blurring into half walls
+                Obstacle::Image { path, invert } => {
+                    mask.add_image(path, FilterType::Nearest, |luma| {
+                        if (luma < 0.5) != *invert {
+                            1.0
+                        } else {
+                            0.0
+                        }
+                    })?
+                }
+            }
+        }
+
+        Ok(Self {
+            food: food.values,
+            mask: mask.values.iter().map(|&v| u32::from(v > 0.0)).collect(),
+        })
+    }
+}
+
+/// A canvas sized layer of values between 0 and 1. Overlapping sources don't add up beyond 1.
+struct Layer {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl Layer {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            values: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    fn fill(&mut self, px: i64, py: i64) {
+        if px < 0 || py < 0 || px >= i64::from(self.width) || py >= i64::from(self.height) {
+            return;
+        }
+
+        self.values[py as usize * self.width as usize + px as usize] = 1.0;
+    }
+
+    /// Columns from `x0` to `x1` that lie on the canvas, so shapes far larger than the canvas
+    /// don't take forever to fill.
+    fn columns(&self, x0: i64, x1: i64) -> RangeInclusive<i64> {
+        x0.max(0)..=x1.min(i64::from(self.width) - 1)
+    }
+
+    /// Rows from `y0` to `y1` that lie on the canvas.
+    fn rows(&self, y0: i64, y1: i64) -> RangeInclusive<i64> {
+        y0.max(0)..=y1.min(i64::from(self.height) - 1)
+    }
+
+    /// Fills the pixels within `radius` of the pixel nearest to `(x, y)`.
+    fn fill_disc(&mut self, x: f32, y: f32, radius: f32) {
+        let (cx, cy) = (x.round(), y.round());
+        let r = radius.max(0.0).ceil();
+
+        for py in self.rows((cy - r) as i64, (cy + r) as i64) {
+            for px in self.columns((cx - r) as i64, (cx + r) as i64) {
+                let (dx, dy) = (px as f32 - cx, py as f32 - cy);
+                if dx * dx + dy * dy <= radius * radius {
+                    self.fill(px, py);
+                }
+            }
+        }
+    }
+
+    /// Fills the pixels whose centre lies within the rectangle.
+    fn fill_rectangle(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let (x0, x1) = (x.round() as i64, (x + width).round() as i64);
+        let (y0, y1) = (y.round() as i64, (y + height).round() as i64);
+
+        for py in self.rows(y0, y1 - 1) {
+            for px in self.columns(x0, x1 - 1) {
+                self.fill(px, py);
+            }
+        }
+    }
+
+    /// Fills the pixels whose centre lies within the polygon, by the even-odd rule.
+    fn fill_polygon(&mut self, points: &[[f32; 2]]) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let min_y = points.iter().map(|p| p[1]).fold(f32::INFINITY, f32::min);
+        let max_y = points
+            .iter()
+            .map(|p| p[1])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let min_x = points.iter().map(|p| p[0]).fold(f32::INFINITY, f32::min);
+        let max_x = points
+            .iter()
+            .map(|p| p[0])
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        for py in self.rows(min_y.floor() as i64, max_y.ceil() as i64) {
+            for px in self.columns(min_x.floor() as i64, max_x.ceil() as i64) {
+                if is_inside_polygon(points, px as f32, py as f32) {
+                    self.fill(px, py);
+                }
+            }
+        }
+    }
+
+    /// Stretches the image over the whole canvas, turning the brightness of every pixel into a
+    /// value with `value_of`.
+    fn add_image(
+        &mut self,
+        path: &Path,
+        filter: FilterType,
+        value_of: impl Fn(f32) -> f32,
+    ) -> Result<(), Error> {
+        let image = image::open(path)?.to_luma32f();
+        let image = image::imageops::resize(&image, self.width, self.height, filter);
+
+        for (value, pixel) in self.values.iter_mut().zip(image.pixels()) {
+            *value = value.max(value_of(pixel.0[0].clamp(0.0, 1.0)));
+        }
+
+        Ok(())
+    }
+}
+
+fn is_inside_polygon(points: &[[f32; 2]], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let ([xi, yi], [xj, yj]) = (points[i], points[j]);
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Reads `x,y` or `x,y,radius` rows, falling back to `radius` where a row has none. Empty lines,
+/// `#` comments and a header row are skipped.
+fn read_csv(path: &Path, radius: f32) -> Result<Vec<[f32; 3]>, Error> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut rows = Vec::new();
+    let mut first_row = true;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header_allowed = std::mem::replace(&mut first_row, false);
+
+        let error = |message: String| Error::Csv {
+            path: path.display().to_string(),
+            line: i + 1,
+            message,
+        };
+
+        let fields: Result<Vec<f32>, _> = line.split(',').map(|f| f.trim().parse()).collect();
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(_) if header_allowed => continue,
+            Err(e) => return Err(error(e.to_string())),
+        };
+
+        match fields[..] {
+            [x, y] => rows.push([x, y, radius]),
+            [x, y, radius] => rows.push([x, y, radius]),
+            _ => {
+                return Err(error(format!(
+                    "expected x,y[,radius], got {} fields",
+                    fields.len()
+                )))
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf};
+
+    use super::*;
+
+    /// Empty directory of its own for every test, as tests run in parallel.
+    fn temporary_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "physarum-environment-{}-{}",
+            std::process::id(),
+            test
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Rows of the layer, `#` where it is filled.
+    fn rows(layer: &Layer) -> Vec<String> {
+        layer
+            .values
+            .chunks(layer.width as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|&v| if v > 0.0 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn discs_partly_off_the_canvas_are_clipped() {
+        let mut layer = Layer::new(5, 4);
+        layer.fill_disc(0.0, 0.0, 1.0);
+        layer.fill_disc(4.2, 3.4, 0.0);
+        assert_eq!(rows(&layer), ["##...", "#....", ".....", "....#"]);
+
+        let mut covered = Layer::new(5, 4);
+        covered.fill_disc(2.0, 2.0, 1e9);
+        assert_eq!(rows(&covered), ["#####"; 4]);
+    }
+
+    #[test]
+    fn discs_off_the_canvas_fill_nothing() {
+        let mut layer = Layer::new(5, 4);
+        layer.fill_disc(-3.0, 1.0, 2.0);
+        layer.fill_disc(5.0, 4.0, 0.9);
+        layer.fill_disc(1e9, -1e9, 1e6);
+        assert_eq!(rows(&layer), ["....."; 4]);
+    }
+
+    #[test]
+    fn rectangles_and_polygons_partly_off_the_canvas_are_clipped() {
+        let mut layer = Layer::new(5, 4);
+        layer.fill_rectangle(3.0, 2.0, 4.0, 5.0);
+        layer.fill_rectangle(-2.0, -1.0, 3.0, 2.0);
+        layer.fill_polygon(&[[-2.5, 3.5], [2.0, 3.5], [-2.5, -1.0]]);
+        assert_eq!(rows(&layer), ["#....", ".....", "#..##", "##.##"]);
+
+        let mut covered = Layer::new(5, 4);
+        covered.fill_rectangle(-1e9, -1e9, 2e9, 2e9);
+        assert_eq!(rows(&covered), ["#####"; 4]);
+    }
+
+    #[test]
+    fn rectangles_and_polygons_off_the_canvas_fill_nothing() {
+        let mut layer = Layer::new(5, 4);
+        layer.fill_rectangle(5.0, 0.0, 3.0, 4.0);
+        layer.fill_rectangle(0.0, -3.0, 5.0, 3.0);
+        layer.fill_rectangle(-1e9, -1e9, 1e6, 1e6);
+        layer.fill_polygon(&[[0.0, 10.0], [4.0, 10.0], [2.0, 14.0]]);
+        layer.fill_polygon(&[[-1e9, 0.0], [-1e6, 0.0], [-1e6, 1e9]]);
+        // Too few points to enclose anything
+        layer.fill_polygon(&[[0.0, 0.0], [4.0, 3.0]]);
+        assert_eq!(rows(&layer), ["....."; 4]);
+    }
+
+    #[test]
+    fn concave_polygons_leave_their_notch_empty() {
+        let mut layer = Layer::new(5, 5);
+        layer.fill_polygon(&[
+            [-0.5, -0.5],
+            [3.5, -0.5],
+            [3.5, 1.5],
+            [1.5, 1.5],
+            [1.5, 3.5],
+            [-0.5, 3.5],
+        ]);
+        assert_eq!(rows(&layer), ["####.", "####.", "##...", "##...", "....."]);
+    }
+
+    #[test]
+    fn images_stretch_over_the_canvas() {
+        let dir = temporary_dir("image");
+        let path = dir.join("walls.png");
+        image::GrayImage::from_raw(2, 1, vec![0, 255])
+            .unwrap()
+            .save(&path)
+            .unwrap();
+
+        let mut layer = Layer::new(4, 2);
+        layer
+            .add_image(&path, FilterType::Nearest, |luma| 1.0 - luma)
+            .unwrap();
+        assert_eq!(rows(&layer), ["##..", "##.."]);
+
+        assert!(matches!(
+            layer.add_image(&dir.join("missing.png"), FilterType::Nearest, |luma| luma),
+            Err(Error::Image(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_csv_rows_skipping_header_and_comments() {
+        let dir = temporary_dir("csv");
+        let path = dir.join("food.csv");
+        fs::write(
+            &path,
+            "# food sources\n\nx, y, radius\n1, 2\n3.5,4,0.5\n  # more\n5,6\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_csv(&path, 2.0).unwrap(),
+            [[1.0, 2.0, 2.0], [3.5, 4.0, 0.5], [5.0, 6.0, 2.0]]
+        );
+
+        let error_line = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            match read_csv(&path, 2.0) {
+                Err(Error::Csv { line, .. }) => line,
+                other => panic!("expected a CSV error, got {:?}", other),
+            }
+        };
+        assert_eq!(error_line("x,y\n1,2\nx,y\n"), 3);
+        assert_eq!(error_line("1,2\n\n1,two\n"), 3);
+        assert_eq!(error_line("# points\n1\n"), 2);
+        assert_eq!(error_line("1,2,3,4\n"), 1);
+
+        assert!(matches!(
+            read_csv(&dir.join("missing.csv"), 2.0),
+            Err(Error::Io(_))
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
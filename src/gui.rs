@@ -0,0 +1,489 @@
+//! Panel over the view for tuning the running simulation with the mouse.
+//!
+//! Shader parameters take effect on the next tick. Initial conditions, the number of agents and
+//! the canvas size only take effect when the simulation is restarted from the panel, and so does
+//! loading a preset.
+
+use std::path::{Path, PathBuf};
+
+use egui::{Button, ClippedPrimitive, ComboBox, Slider, TexturesDelta, Ui};
+use egui_wgpu::ScreenDescriptor;
+use winit::{event::WindowEvent, window::Window};
+
+use crate::{
+    environment,
+    parameters::{
+        BoundaryMode, DiffusionEdgeMode, InitialHeading, Parameters, ShaderParameters, MAX_SPECIES,
+    },
+    presets::{self, Preset},
+    Simulation,
+};
+
+/// Largest canvas side the panel offers, to stay within what GPUs can hold.
+const MAX_CANVAS_SIDE: u32 = 8192;
+
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    pub visible: bool,
+    /// What the next restart starts from, as edited in the panel.
+    restart_with: Parameters,
+    /// Laid out by `run`, waiting to be drawn.
+    frame: Option<Frame>,
+    preset_dir: Option<PathBuf>,
+    /// Listed once and again after every save, rather than reading the directory every frame.
+    presets: Vec<Preset>,
+    preset_name: String,
+    /// Outcome of the last preset loaded or saved.
+    preset_status: String,
+}
+
+enum PresetAction {
+    Load,
+    Save,
+}
+
+struct Frame {
+    primitives: Vec<ClippedPrimitive>,
+    textures_delta: TexturesDelta,
+    screen: ScreenDescriptor,
+}
+
+impl Gui {
+    pub fn new(
+        window: &Window,
+        device: &wgpu::Device,
+        target_format: wgpu::TextureFormat,
+        params: &Parameters,
+        preset_dir: Option<PathBuf>,
+    ) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, target_format, None, 1);
+
+        Self {
+            context,
+            state,
+            renderer,
+            visible: true,
+            restart_with: params.clone(),
+            frame: None,
+            presets: list_presets(preset_dir.as_deref()),
+            preset_dir,
+            preset_name: String::new(),
+            preset_status: String::new(),
+        }
+    }
+
+    /// Passes window input to the panel. Returns `true` when the panel used it, so the viewer
+    /// should not act on it too.
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.visible && self.state.on_window_event(window, event).consumed
+    }
+
+    /// Lays out the panel for this frame and applies whatever was changed in it to the
+    /// simulation. Returns `true` when the simulation was restarted, which replaces its resources.
+    pub fn run(
+        &mut self,
+        window: &Window,
+        simulation: &mut Simulation,
+    ) -> Result<bool, environment::Error> {
+        if !self.visible {
+            return Ok(false);
+        }
+
+        let mut ctx = simulation.params().shader_parameters;
+        let mut restart = false;
+        let mut preset_action = None;
+
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, |context| {
+            egui::Window::new("Parameters")
+                .default_width(320.0)
+                .show(context, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        preset_action = presets_ui(
+                            ui,
+                            &self.presets,
+                            &mut self.preset_name,
+                            &self.preset_status,
+                            self.preset_dir.is_some(),
+                        );
+                        ui.separator();
+                        shader_parameters_ui(ui, &mut ctx, simulation.params());
+                        ui.separator();
+                        restart = initial_conditions_ui(ui, &mut self.restart_with);
+                    });
+                });
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        if ctx != simulation.params().shader_parameters {
+            simulation.set_shader_parameters(ctx);
+        }
+
+        let size = window.inner_size();
+        self.frame = Some(Frame {
+            primitives: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            screen: ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: output.pixels_per_point,
+            },
+        });
+
+        if restart {
+            let mut params = simulation.params().clone();
+            params.number_of_agents = self.restart_with.number_of_agents;
+            params.seed = self.restart_with.seed;
+            params.initial_conditions = self.restart_with.initial_conditions.clone();
+            params.shader_parameters.canvas_width =
+                self.restart_with.shader_parameters.canvas_width;
+            params.shader_parameters.canvas_height =
+                self.restart_with.shader_parameters.canvas_height;
+            params.shader_parameters.number_of_species =
+                self.restart_with.shader_parameters.number_of_species;
+
+            simulation.restart(params)?;
+        }
+
+        match preset_action {
+            Some(PresetAction::Load) => {
+                match presets::load(&self.preset_name, self.preset_dir.as_deref()) {
+                    Ok(params) => {
+                        self.restart_with = params.clone();
+                        simulation.restart(params)?;
+                        self.preset_status = format!("Loaded '{}'", self.preset_name);
+                        return Ok(true);
+                    }
+                    Err(e) => self.preset_status = e.to_string(),
+                }
+            }
+            Some(PresetAction::Save) => self.save_preset(simulation.params()),
+            None => (),
+        }
+
+        Ok(restart)
+    }
+
+    fn save_preset(&mut self, params: &Parameters) {
+        let Some(preset_dir) = &self.preset_dir else {
+            return;
+        };
+
+        // The seed drawn for this run would make every run of the preset the same
+        let mut params = params.clone();
+        params.seed = self.restart_with.seed;
+
+        self.preset_status = match presets::save(&params, &self.preset_name, preset_dir) {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(e) => e.to_string(),
+        };
+        self.presets = list_presets(Some(preset_dir));
+    }
+
+    /// Draws the panel laid out by the last `run` over `target`. Returns command buffers that
+    /// must be submitted before `command_encoder`.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let Some(frame) = self.frame.take() else {
+            return Vec::new();
+        };
+
+        for (id, image_delta) in &frame.textures_delta.set {
+            self.renderer
+                .update_texture(device, queue, *id, image_delta);
+        }
+
+        let command_buffers = self.renderer.update_buffers(
+            device,
+            queue,
+            command_encoder,
+            &frame.primitives,
+            &frame.screen,
+        );
+
+        {
+            let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("gui-render-pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            self.renderer
+                .render(&mut render_pass, &frame.primitives, &frame.screen);
+        }
+
+        for id in &frame.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+
+        command_buffers
+    }
+}
+
+/// Picks, loads and saves presets. Returns what was clicked, if anything.
+fn presets_ui(
+    ui: &mut Ui,
+    presets: &[Preset],
+    name: &mut String,
+    status: &str,
+    can_save: bool,
+) -> Option<PresetAction> {
+    let mut action = None;
+
+    egui::CollapsingHeader::new("Presets")
+        .default_open(false)
+        .show(ui, |ui| {
+            ComboBox::from_label("preset")
+                .selected_text(name.as_str())
+                .show_ui(ui, |ui| {
+                    for preset in presets {
+                        ui.selectable_value(name, preset.name.clone(), &preset.name);
+                    }
+                });
+
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(name);
+                if ui.button("Load").clicked() {
+                    action = Some(PresetAction::Load);
+                }
+                if ui.add_enabled(can_save, Button::new("Save")).clicked() {
+                    action = Some(PresetAction::Save);
+                }
+            });
+
+            if !status.is_empty() {
+                ui.label(status);
+            }
+        });
+
+    action
+}
+
+fn shader_parameters_ui(ui: &mut Ui, ctx: &mut ShaderParameters, params: &Parameters) {
+    ui.label(format!(
+        "{} × {} canvas, {} species, tick {}",
+        ctx.canvas_width, ctx.canvas_height, ctx.number_of_species, params.tick
+    ));
+
+    ui.horizontal(|ui| {
+        if ui.button("Reset").clicked() {
+            *ctx = ShaderParameters::default();
+        }
+        if ui.button("Randomize").clicked() {
+            ctx.randomize();
+        }
+    });
+
+    egui::CollapsingHeader::new("Agents")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.add(Slider::new(&mut ctx.agent_speed, 0.0..=10.0).text("agent speed"));
+            ui.add(
+                Slider::new(
+                    &mut ctx.number_of_active_agents,
+                    0..=params.number_of_agents,
+                )
+                .text("active agents"),
+            );
+            ui.add(Slider::new(&mut ctx.sensor_angle_degrees, 0.0..=180.0).text("sensor angle"));
+            ui.add(Slider::new(&mut ctx.sensor_distance, 0.0..=100.0).text("sensor distance"));
+            ui.add(
+                Slider::new(&mut ctx.max_turn_angle_degrees, 0.0..=180.0).text("max turn angle"),
+            );
+            ui.add(
+                Slider::new(&mut ctx.max_rand_turn_angle_degrees, 0.0..=45.0)
+                    .text("max random turn angle"),
+            );
+            ui.add(
+                Slider::new(&mut ctx.deposit_strength, 0.0001..=1.0)
+                    .logarithmic(true)
+                    .text("deposit strength"),
+            );
+            ui.add(
+                Slider::new(&mut ctx.high_density_threshold, 0.0..=1.0)
+                    .text("high density threshold"),
+            );
+            ui.add(
+                Slider::new(&mut ctx.high_density_speed_boost, 0.0..=20.0)
+                    .text("high density speed boost"),
+            );
+            choice(
+                ui,
+                "boundary mode",
+                &mut ctx.boundary_mode,
+                &[
+                    BoundaryMode::Wrap,
+                    BoundaryMode::Reflect,
+                    BoundaryMode::Absorb,
+                    BoundaryMode::RespawnRandom,
+                    BoundaryMode::RespawnInitial,
+                ],
+            );
+
+            flag(ui, &mut ctx.bool_enable_agent_deposit, "deposit");
+            flag(ui, &mut ctx.bool_enable_agent_rotate, "rotate");
+            flag(ui, &mut ctx.bool_enable_agent_rotate_left, "rotate left");
+            flag(ui, &mut ctx.bool_enable_agent_rotate_right, "rotate right");
+            flag(
+                ui,
+                &mut ctx.bool_enable_agent_rotate_randomly,
+                "rotate randomly",
+            );
+            flag(
+                ui,
+                &mut ctx.bool_enable_high_density_dispersion,
+                "high density dispersion",
+            );
+        });
+
+    egui::CollapsingHeader::new("Trail")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.add(
+                Slider::new(&mut ctx.decay_strength, 0.0001..=1.0)
+                    .logarithmic(true)
+                    .text("decay strength"),
+            );
+            ui.add(
+                Slider::new(&mut ctx.food_deposit_strength, 0.0..=1.0)
+                    .text("food deposit strength"),
+            );
+            choice(
+                ui,
+                "diffusion edges",
+                &mut ctx.diffusion_edge_mode,
+                &[
+                    DiffusionEdgeMode::Zero,
+                    DiffusionEdgeMode::Clamp,
+                    DiffusionEdgeMode::Wrap,
+                    DiffusionEdgeMode::Mirror,
+                    DiffusionEdgeMode::Renormalize,
+                ],
+            );
+
+            flag(ui, &mut ctx.bool_enable_diffuse, "diffuse");
+            flag(ui, &mut ctx.bool_enable_decay, "decay");
+        });
+
+    egui::CollapsingHeader::new("Rendering")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.add(Slider::new(&mut ctx.vertex_stretch, 0.0..=1.0).text("vertex stretch"));
+            flag(
+                ui,
+                &mut ctx.bool_enable_render_trail_map,
+                "render trail map",
+            );
+            flag(ui, &mut ctx.bool_enable_color, "color");
+        });
+}
+
+/// Edits what the next restart starts from. Returns `true` when restart was clicked.
+fn initial_conditions_ui(ui: &mut Ui, params: &mut Parameters) -> bool {
+    let mut restart = false;
+
+    egui::CollapsingHeader::new("Initial conditions")
+        .default_open(false)
+        .show(ui, |ui| {
+            let ctx = &mut params.shader_parameters;
+            ui.add(Slider::new(&mut ctx.canvas_width, 1..=MAX_CANVAS_SIDE).text("canvas width"));
+            ui.add(Slider::new(&mut ctx.canvas_height, 1..=MAX_CANVAS_SIDE).text("canvas height"));
+            ui.add(Slider::new(&mut ctx.number_of_species, 1..=MAX_SPECIES as u32).text("species"));
+            ui.add(
+                Slider::new(&mut params.number_of_agents, 1..=10_000_000)
+                    .logarithmic(true)
+                    .text("agents"),
+            );
+
+            let initial_conditions = &mut params.initial_conditions;
+            ui.add(
+                Slider::new(&mut initial_conditions.initial_circle_radius, 0.0..=2000.0)
+                    .text("initial circle radius"),
+            );
+            choice(
+                ui,
+                "initial heading",
+                &mut initial_conditions.initial_heading,
+                &[
+                    InitialHeading::Inward,
+                    InitialHeading::Outward,
+                    InitialHeading::Random,
+                ],
+            );
+
+            ui.horizontal(|ui| {
+                let mut fixed_seed = params.seed.is_some();
+                ui.checkbox(&mut fixed_seed, "fixed seed");
+                match (fixed_seed, &mut params.seed) {
+                    (true, Some(seed)) => {
+                        ui.add(egui::DragValue::new(seed));
+                    }
+                    (true, seed @ None) => *seed = Some(0),
+                    (false, seed) => *seed = None,
+                }
+            });
+
+            restart = ui.button("Restart").clicked();
+        });
+
+    restart
+}
+
+/// Checkbox for one of the `bool_enable_*` flags, which the shader reads as 0 or 1.
+fn flag(ui: &mut Ui, value: &mut u32, text: &str) {
+    let mut checked = *value != 0;
+    if ui.checkbox(&mut checked, text).changed() {
+        *value = u32::from(checked);
+    }
+}
+
+fn choice<T: Copy + PartialEq + std::fmt::Debug>(
+    ui: &mut Ui,
+    text: &str,
+    value: &mut T,
+    options: &[T],
+) {
+    ComboBox::from_label(text)
+        .selected_text(format!("{:?}", value))
+        .show_ui(ui, |ui| {
+            for option in options {
+                ui.selectable_value(value, *option, format!("{:?}", option));
+            }
+        });
+}
+
+/// Presets to offer, or none but the bundled ones when the preset directory cannot be read.
+fn list_presets(preset_dir: Option<&Path>) -> Vec<Preset> {
+    presets::list(preset_dir)
+        .or_else(|e| {
+            eprintln!("Could not list presets: {}", e);
+            presets::list(None)
+        })
+        .unwrap_or_default()
+}
@@ -1,89 +1,135 @@
-use std::sync::Arc;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+use typed_builder::TypedBuilder;
 use wgpu::{Backends, Instance, InstanceDescriptor, Surface, SurfaceConfiguration};
 use winit::{
-    dpi::PhysicalSize,
-    event::{Event, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowBuilder},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
-mod agent;
-mod device;
-mod parameters;
-mod pipelines;
-mod resources;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    color: [f32; 3],
-}
+pub mod agent;
+pub mod backend;
+pub mod brush;
+pub mod capture;
+pub mod checkpoint;
+pub mod config;
+pub mod controls;
+pub mod cpu;
+pub mod device;
+pub mod environment;
+pub mod gui;
+pub mod parameters;
+pub mod pipelines;
+pub mod presets;
+pub mod resources;
+pub mod simulation;
+pub mod video;
+pub mod view;
+
+pub use simulation::Simulation;
+
+use brush::{Brush, Tool};
+use checkpoint::Checkpoint;
+use controls::{Action, KeyBindings};
+use gui::Gui;
+use view::{View, ViewParameters};
+
+/// How much one line of the scroll wheel zooms in.
+const ZOOM_PER_LINE: f32 = 1.1;
+/// Pixels of touchpad scrolling that count as one line of the scroll wheel.
+const PIXELS_PER_LINE: f32 = 40.0;
+/// How long the window size has to stay put before the grid is resampled to it.
+const RESAMPLE_DELAY: Duration = Duration::from_millis(250);
+
+/// What the viewer starts with.
+#[derive(TypedBuilder)]
+pub struct ViewerOptions {
+    params: parameters::Parameters,
 
-impl Vertex {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-            ],
-        }
-    }
-}
+    /// State to continue from instead of a fresh initial distribution.
+    #[builder(default)]
+    checkpoint: Option<Checkpoint>,
+
+    /// Directory checkpoints taken with `C` are written to.
+    #[builder(default = PathBuf::from("output"))]
+    output: PathBuf,
+
+    /// Resample the simulation grid to the window size whenever the window is resized, instead of
+    /// letterboxing the canvas as it is.
+    #[builder(default)]
+    resample_grid: bool,
 
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [0.0, 0.5, 0.0],
-        color: [1.0, 0.0, 0.0],
-    },
-    Vertex {
-        position: [-0.5, -0.5, 0.0],
-        color: [0.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [0.5, -0.5, 0.0],
-        color: [0.0, 0.0, 1.0],
-    },
-];
+    /// Radius of the brush painted with the right mouse button, in canvas pixels.
+    #[builder(default = 8.0)]
+    brush_radius: f32,
+
+    /// Trail or food the brush adds in the middle of a stroke, between 0 and 1.
+    #[builder(default = 0.5)]
+    brush_strength: f32,
+
+    /// Keys that change the shader parameters.
+    #[builder(default)]
+    key_bindings: KeyBindings,
+
+    /// Start with the parameter panel open. `F1` shows and hides it either way.
+    #[builder(default = true)]
+    show_gui: bool,
+
+    /// Directory presets saved from the parameter panel go to, and are loaded from.
+    #[builder(default)]
+    preset_dir: Option<PathBuf>,
+}
 
 struct State<'window> {
-    params: parameters::Parameters,
+    /// Shared between the ticker task and the event loop. Holding the lock also serializes their
+    /// GPU submissions and parameter changes.
+    simulation: Mutex<Simulation>,
     surface: Surface<'window>,
-    device: device::Device,
-    config: SurfaceConfiguration,
-    resources: resources::Resources,
-    pipelines: pipelines::Pipelines,
+    /// The surface configuration and view follow the window size. Lock the simulation first.
+    config: Mutex<SurfaceConfiguration>,
+    view: Mutex<View>,
+    /// Rebuilt whenever resampling the grid or restarting replaces the simulation resources.
+    render_pipeline: Mutex<wgpu::RenderPipeline>,
+    gui: Mutex<Gui>,
     window: Arc<Window>,
-    gpu_mutex: Arc<std::sync::Mutex<()>>,
+    output: PathBuf,
+    resample_grid: bool,
+    /// When to resample the grid to the window, once it has stopped changing size.
+    resample_at: Mutex<Option<Instant>>,
+    brush: Mutex<Brush>,
+    key_bindings: KeyBindings,
+    /// Last checkpoint taken, which `Backspace` returns to.
+    saved: Mutex<Option<Checkpoint>>,
 }
 
 impl<'window> State<'window> {
-    async fn new(window: Window) -> State<'window> {
+    async fn new(
+        window: Window,
+        options: ViewerOptions,
+    ) -> Result<State<'window>, environment::Error> {
+        let ViewerOptions {
+            params,
+            checkpoint,
+            output,
+            resample_grid,
+            brush_radius,
+            brush_strength,
+            key_bindings,
+            show_gui,
+            preset_dir,
+        } = options;
+
         let size = window.inner_size();
 
         let window = Arc::new(window);
 
-        let params = parameters::Parameters::builder()
-            .shader_parameters(
-                parameters::ShaderParameters::builder()
-                    .canvas_width(size.width)
-                    .canvas_height(size.height)
-                    .build(),
-            )
-            .build();
-
         // Context for all other wgpu objects.
         let instance = Instance::new(InstanceDescriptor {
             backends: Backends::all(),
@@ -96,96 +142,237 @@ impl<'window> State<'window> {
 
         let config = configure_surface(&device, &surface, size);
 
-        let resources = resources::Resources::new(&device.device, &params);
-
-        let pipelines = pipelines::Pipelines::new(&device.device, config.format, &resources);
-
-        Self {
-            params,
+        let simulation = match checkpoint {
+            Some(checkpoint) => Simulation::from_checkpoint(device, &checkpoint)?,
+            None => Simulation::new(device, params)?,
+        };
+
+        let view = View::new(&simulation.device.device, size.width, size.height);
+
+        let render_pipeline = simulation.pipelines.create_render_pipeline(
+            &simulation.device.device,
+            &simulation.resources,
+            &view.resource.bind_group_layout,
+            config.format,
+        );
+
+        let mut gui = Gui::new(
+            &window,
+            &simulation.device.device,
+            config.format,
+            simulation.params(),
+            preset_dir,
+        );
+        gui.visible = show_gui;
+
+        Ok(Self {
+            simulation: Mutex::new(simulation),
             surface,
-            device,
-            config,
-            resources,
-            pipelines,
+            config: Mutex::new(config),
+            view: Mutex::new(view),
+            render_pipeline: Mutex::new(render_pipeline),
+            gui: Mutex::new(gui),
             window,
-            gpu_mutex: Arc::new(std::sync::Mutex::new(())),
-        }
+            output,
+            resample_grid,
+            resample_at: Mutex::new(None),
+            brush: Mutex::new(Brush::new(brush_radius, brush_strength)),
+            key_bindings,
+            saved: Mutex::new(None),
+        })
     }
 
     fn update(&self) {
-        let gpu_lock = self.gpu_mutex.lock().unwrap();
+        self.simulation.lock().unwrap().step();
+    }
 
-        // Start a new command encoder
-        let mut command_encoder =
-            self.device
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("command-encoder"),
-                });
+    fn time_per_tick(&self) -> Duration {
+        let target_ticks_per_second = self
+            .simulation
+            .lock()
+            .unwrap()
+            .params()
+            .target_ticks_per_second;
 
-        // Diffuse and decay
-        {
-            let mut compute_pass =
-                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: Some("diffuse-and-decay-cp"),
-                    timestamp_writes: None,
-                });
+        Duration::from_secs_f32(1.0 / target_ticks_per_second)
+    }
+
+    /// Applies a key binding and prints what it changed, in a form that can be pasted into a
+    /// config file.
+    fn apply_action(&self, action: Action) {
+        let mut simulation = self.simulation.lock().unwrap();
+        simulation.update_shader_parameters(|p| action.apply(p));
+        let ctx = simulation.params().shader_parameters;
+
+        match action.parameter() {
+            Some(parameter) => println!("{}", parameter.describe(&ctx)),
+            None => match toml::to_string(&ctx) {
+                Ok(toml) => println!("[shader_parameters]\n{}", toml),
+                Err(e) => eprintln!("Could not print the shader parameters: {}", e),
+            },
+        }
+    }
+
+    fn save_checkpoint(&self) -> Result<(), checkpoint::Error> {
+        let checkpoint = Checkpoint::capture(&*self.simulation.lock().unwrap());
+
+        // Milliseconds, so checkpoints taken in quick succession don't overwrite each other
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = self
+            .output
+            .join(format!("physarum_{}.checkpoint", timestamp));
+
+        std::fs::create_dir_all(&self.output)?;
+        checkpoint.save(&path)?;
+        println!("Saved checkpoint to {}", path.display());
+
+        *self.saved.lock().unwrap() = Some(checkpoint);
+
+        Ok(())
+    }
+
+    /// Returns to the last checkpoint taken, keeping the parameters currently in use.
+    fn restore_checkpoint(&self) -> Result<(), checkpoint::Error> {
+        let Some(saved) = self.saved.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        self.simulation.lock().unwrap().restore(&saved)
+    }
 
-            compute_pass.set_pipeline(&self.pipelines.diffuse_and_decay);
-            compute_pass.set_bind_group(0, &self.resources.shader_context.bind_group, &[]);
-            compute_pass.set_bind_group(1, &self.resources.data_layer.bind_group, &[]);
-            compute_pass.set_bind_group(2, &self.resources.trail_layer.bind_group, &[]);
+    /// Reconfigures the surface for the window's current size and, if asked to, schedules
+    /// resampling the grid to it.
+    fn resize(&self) {
+        let size = self.window.inner_size();
 
-            compute_pass.dispatch_workgroups(
-                self.params.shader_parameters.canvas_width / 8,
-                self.params.shader_parameters.canvas_height / 8,
-                1,
-            );
+        // Minimized windows have no area to present to
+        if size.width == 0 || size.height == 0 {
+            return;
         }
 
-        // Move agents and deposit
-        {
-            let mut compute_pass =
-                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: Some("agent-sense-move-deposit-cp"),
-                    timestamp_writes: None,
-                });
+        let simulation = self.simulation.lock().unwrap();
+
+        let config = configure_surface(&simulation.device, &self.surface, size);
+        self.view
+            .lock()
+            .unwrap()
+            .resize(&simulation.device.queue, size.width, size.height);
+        *self.config.lock().unwrap() = config;
+
+        if self.resample_grid {
+            *self.resample_at.lock().unwrap() = Some(Instant::now() + RESAMPLE_DELAY);
+        }
+    }
+
+    /// Resamples the grid to the window once it has settled on a size.
+    ///
+    /// Dragging the window edge resizes it many times over. Waiting for the last of them resamples
+    /// the grid the drag started from just once, instead of blurring it further with every step.
+    fn resample_if_settled(&self) -> Result<(), environment::Error> {
+        let mut resample_at = self.resample_at.lock().unwrap();
+        if !resample_at.is_some_and(|at| Instant::now() >= at) {
+            return Ok(());
+        }
+        *resample_at = None;
+        drop(resample_at);
 
-            compute_pass.set_pipeline(&self.pipelines.agent_sense_move_deposit);
-            compute_pass.set_bind_group(0, &self.resources.shader_context.bind_group, &[]);
-            compute_pass.set_bind_group(1, &self.resources.data_layer.bind_group, &[]);
-            compute_pass.set_bind_group(2, &self.resources.trail_layer.bind_group, &[]);
+        let size = self.window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return Ok(());
+        }
 
-            // Lay agents out in x and y so they can be mapped to shader workgroups
-            let number_of_active_agents = self.params.shader_parameters.number_of_active_agents;
+        let mut simulation = self.simulation.lock().unwrap();
+        simulation.resize_canvas(size.width, size.height)?;
 
-            // Must match what is in the shader code
-            const WORKGROUP_SIZE_X: u32 = 8;
-            const WORKGROUP_SIZE_Y: u32 = 8;
-            const WORKGROUP_SIZE_Z: u32 = 1;
+        let format = self.config.lock().unwrap().format;
+        self.rebuild_render_pipeline(&simulation, &self.view.lock().unwrap(), format);
 
-            let threads_per_workgroup = WORKGROUP_SIZE_X * WORKGROUP_SIZE_Y * WORKGROUP_SIZE_Z;
+        Ok(())
+    }
 
-            // Hacked integer division: number_of_active_agents / threads_per_workgroup but hacked so it rounds up
-            let workgroups_needed =
-                (number_of_active_agents + (threads_per_workgroup - 1)) / threads_per_workgroup;
+    /// Recreates the render pipeline after the simulation resources were replaced.
+    fn rebuild_render_pipeline(
+        &self,
+        simulation: &Simulation,
+        view: &View,
+        format: wgpu::TextureFormat,
+    ) {
+        *self.render_pipeline.lock().unwrap() = simulation.pipelines.create_render_pipeline(
+            &simulation.device.device,
+            &simulation.resources,
+            &view.resource.bind_group_layout,
+            format,
+        );
+    }
+
+    /// Lays out the parameter panel for the next frame, applying what was changed in it.
+    fn update_gui(&self) -> Result<(), environment::Error> {
+        let mut simulation = self.simulation.lock().unwrap();
 
-            const NUMBER_OF_WORKGROUPS_X: u32 = 32;
-            // Hacked integer division: workgroups_needed / 32 but hacked so it rounds up
-            let number_of_workgroups_y = (workgroups_needed + 31) / 32;
-            let number_of_workgroups_z = 1;
+        let restarted = self
+            .gui
+            .lock()
+            .unwrap()
+            .run(&self.window, &mut simulation)?;
 
-            compute_pass.dispatch_workgroups(
-                NUMBER_OF_WORKGROUPS_X,
-                number_of_workgroups_y,
-                number_of_workgroups_z,
-            );
+        if restarted {
+            let format = self.config.lock().unwrap().format;
+            self.rebuild_render_pipeline(&simulation, &self.view.lock().unwrap(), format);
         }
 
-        let command_buffer = command_encoder.finish();
-        self.device.queue.submit(Some(command_buffer));
+        Ok(())
+    }
+
+    /// Passes input to the parameter panel. Returns `true` when the panel used it.
+    fn gui_event(&self, event: &WindowEvent) -> bool {
+        self.gui
+            .lock()
+            .unwrap()
+            .on_window_event(&self.window, event)
+    }
+
+    fn toggle_gui(&self) {
+        let mut gui = self.gui.lock().unwrap();
+        gui.visible = !gui.visible;
+    }
+
+    /// Changes the view in place, e.g. to move the camera over the canvas.
+    fn update_view(&self, update: impl FnOnce(&mut ViewParameters, &parameters::ShaderParameters)) {
+        let simulation = self.simulation.lock().unwrap();
+        let ctx = simulation.params().shader_parameters;
+
+        self.view
+            .lock()
+            .unwrap()
+            .update(&simulation.device.queue, |params| update(params, &ctx));
+    }
+
+    /// Paints a stroke of the brush at the surface position `(x, y)`.
+    fn paint(&self, x: f32, y: f32) {
+        let mut simulation = self.simulation.lock().unwrap();
+        let ctx = simulation.params().shader_parameters;
+
+        let [x, y] = self.view.lock().unwrap().params.canvas_position(&ctx, x, y);
+        let stroke = self.brush.lock().unwrap().stroke(x, y);
+        simulation.paint(&stroke);
+    }
+
+    fn update_brush(&self, update: impl FnOnce(&mut Brush)) {
+        let mut brush = self.brush.lock().unwrap();
+        update(&mut brush);
+        println!("Brush: {}", brush);
+    }
+
+    fn toggle_fullscreen(&self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
 
-        drop(gpu_lock);
+        self.window.set_fullscreen(fullscreen);
     }
 
     fn render(&self) -> Result<(), wgpu::SurfaceError> {
@@ -195,10 +382,14 @@ impl<'window> State<'window> {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let gpu_lock = self.gpu_mutex.lock().unwrap();
+        let simulation = self.simulation.lock().unwrap();
+        let render_pipeline = self.render_pipeline.lock().unwrap();
+        let view = self.view.lock().unwrap();
+
+        let device = &simulation.device;
 
         let mut command_encoder =
-            self.device
+            device
                 .device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("render-command-encoder"),
@@ -220,18 +411,29 @@ impl<'window> State<'window> {
                 occlusion_query_set: None,
             });
 
-            render_pass.set_pipeline(&self.pipelines.render_pipeline);
-            render_pass.set_bind_group(0, &self.resources.shader_context.bind_group, &[]);
-            render_pass.set_bind_group(1, &self.resources.data_layer.bind_group, &[]);
-            render_pass.set_bind_group(2, &self.resources.trail_layer.bind_group, &[]);
+            render_pass.set_pipeline(&render_pipeline);
+            simulation.set_bind_groups(&mut render_pass);
+            render_pass.set_bind_group(3, &view.resource.bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
 
-        self.device
-            .queue
-            .submit(std::iter::once(command_encoder.finish()));
+        // The panel goes on top
+        let gui_command_buffers = self.gui.lock().unwrap().draw(
+            &device.device,
+            &device.queue,
+            &mut command_encoder,
+            &texture_view,
+        );
+
+        device.queue.submit(
+            gui_command_buffers
+                .into_iter()
+                .chain(Some(command_encoder.finish())),
+        );
 
-        drop(gpu_lock);
+        drop(view);
+        drop(render_pipeline);
+        drop(simulation);
 
         surface_texture.present();
 
@@ -250,8 +452,7 @@ fn configure_surface(
         .formats
         .iter()
         .copied()
-        .filter(|f| f.is_srgb())
-        .next()
+        .find(|f| f.is_srgb())
         .unwrap_or(caps.formats[0]);
 
     let config = SurfaceConfiguration {
@@ -270,87 +471,197 @@ fn configure_surface(
     config
 }
 
-pub async fn run() {
+/// Opens a window and runs the simulation in it until the window is closed.
+///
+/// Fails if the environment (e.g. a food image) cannot be loaded.
+pub async fn run(options: ViewerOptions) -> Result<(), environment::Error> {
     let event_loop = EventLoop::new().unwrap();
 
+    let canvas = match &options.checkpoint {
+        Some(checkpoint) => checkpoint.params.shader_parameters,
+        None => options.params.shader_parameters,
+    };
+
+    // Canvases larger than the screen are shown scaled down
+    let mut size = PhysicalSize::new(canvas.canvas_width, canvas.canvas_height);
+    if let Some(monitor) = event_loop.primary_monitor() {
+        let screen = monitor.size();
+        let scale = (0.9 * f64::from(screen.width) / f64::from(size.width))
+            .min(0.9 * f64::from(screen.height) / f64::from(size.height))
+            .min(1.0);
+        size = PhysicalSize::new(
+            (f64::from(size.width) * scale) as u32,
+            (f64::from(size.height) * scale) as u32,
+        );
+    }
+
     let window_builder = WindowBuilder::new()
         .with_title("Physarum")
-        .with_inner_size(PhysicalSize::new(1400, 1400))
-        .with_resizable(false);
+        .with_inner_size(size);
 
     let window = window_builder.build(&event_loop).unwrap();
 
-    let state = Arc::new(State::new(window).await);
+    let state = Arc::new(State::new(window, options).await?);
 
     // Spawn thread to drive the simulation forward by dispatching GPU commands at e.g. 60 FPS
-    let ticker = {
+    let _ticker = {
         let state_tick = Arc::clone(&state);
         tokio::spawn(async move {
             loop {
                 state_tick.update();
 
-                tokio::time::sleep(std::time::Duration::from_nanos(
-                    1_000_000_000 / state_tick.params.target_ticks_per_second as u64,
-                ))
-                .await;
+                tokio::time::sleep(state_tick.time_per_tick()).await;
             }
         })
     };
 
+    // Where the cursor is on the surface, and whether it is dragging the canvas along or painting
+    let mut cursor = PhysicalPosition::new(0.0, 0.0);
+    let mut panning = false;
+    let mut painting = false;
+    let mut modifiers = ModifiersState::empty();
+
     event_loop
         .run(move |event, elwt| {
             match event {
-                Event::NewEvents(cause) => match cause {
-                    winit::event::StartCause::ResumeTimeReached { .. } => {
-                        state.window.request_redraw();
-                    }
-                    _ => {}
-                },
-                Event::WindowEvent { window_id, event } => {
-                    // println!("Window event: {:?}, {:?}", window_id, event);
-
-                    if window_id == state.window.id() {
-                        match event {
-                            WindowEvent::CloseRequested => {
-                                elwt.exit();
+                Event::NewEvents(winit::event::StartCause::ResumeTimeReached { .. }) => {
+                    state.window.request_redraw();
+                }
+                Event::WindowEvent { window_id, event } if window_id == state.window.id() => {
+                    // The panel sees input first, so e.g. dragging a slider does not also pan
+                    let used_by_gui = state.gui_event(&event);
+
+                    match event {
+                        WindowEvent::CloseRequested => {
+                            elwt.exit();
+                        }
+                        WindowEvent::Resized(_) => state.resize(),
+                        WindowEvent::RedrawRequested => {
+                            let next_frame = Instant::now() + state.time_per_tick();
+                            elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+
+                            if let Err(e) = state.resample_if_settled() {
+                                eprintln!("Could not resize the canvas: {}", e);
                             }
-                            WindowEvent::RedrawRequested => {
-                                let time_per_frame = std::time::Duration::from_micros(
-                                    1_000_000 / state.params.target_ticks_per_second as u64,
-                                );
-                                let next_frame = std::time::Instant::now() + time_per_frame;
-                                elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
-
-                                match state.render() {
-                                    Ok(_) => {}
-                                    Err(wgpu::SurfaceError::Lost) => {
-                                        state
-                                            .surface
-                                            .configure(&state.device.device, &state.config);
-                                    }
-                                    Err(wgpu::SurfaceError::OutOfMemory) => {
-                                        eprintln!("Out of memory");
-                                        elwt.exit();
-                                    }
-                                    Err(e) => eprintln!("{:?}", e),
+
+                            if let Err(e) = state.update_gui() {
+                                eprintln!("Could not restart the simulation: {}", e);
+                            }
+
+                            match state.render() {
+                                Ok(_) => {}
+                                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                    state.resize()
+                                }
+                                Err(wgpu::SurfaceError::OutOfMemory) => {
+                                    eprintln!("Out of memory");
+                                    elwt.exit();
                                 }
+                                Err(e) => eprintln!("{:?}", e),
+                            }
+                        }
+                        WindowEvent::MouseInput {
+                            state: button_state,
+                            button: MouseButton::Left,
+                            ..
+                        } => {
+                            panning = button_state == ElementState::Pressed && !used_by_gui;
+                        }
+                        WindowEvent::MouseInput {
+                            state: button_state,
+                            button: MouseButton::Right,
+                            ..
+                        } => {
+                            painting = button_state == ElementState::Pressed && !used_by_gui;
+                            if painting {
+                                state.paint(cursor.x as f32, cursor.y as f32);
                             }
-                            WindowEvent::KeyboardInput { event, .. } => {
-                                if event.state == winit::event::ElementState::Pressed {
-                                    match event.physical_key {
-                                        PhysicalKey::Code(code) => match code {
-                                            KeyCode::Escape => elwt.exit(),
-                                            KeyCode::KeyR => {
-                                                // state.params.shader_parameters.randomize();
-                                            }
-                                            _ => (),
-                                        },
-                                        _ => (),
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            if panning {
+                                let dx = (position.x - cursor.x) as f32;
+                                let dy = (position.y - cursor.y) as f32;
+                                state.update_view(|view, ctx| view.pan(ctx, dx, dy));
+                            }
+                            if painting {
+                                state.paint(position.x as f32, position.y as f32);
+                            }
+                            cursor = position;
+                        }
+                        WindowEvent::MouseWheel { delta, .. } if !used_by_gui => {
+                            let lines = match delta {
+                                MouseScrollDelta::LineDelta(_, y) => y,
+                                MouseScrollDelta::PixelDelta(position) => {
+                                    position.y as f32 / PIXELS_PER_LINE
+                                }
+                            };
+                            let factor = ZOOM_PER_LINE.powf(lines);
+                            state.update_view(|view, ctx| {
+                                view.zoom_at(ctx, factor, cursor.x as f32, cursor.y as f32)
+                            });
+                        }
+                        WindowEvent::ModifiersChanged(new_modifiers) => {
+                            modifiers = new_modifiers.state();
+                        }
+                        WindowEvent::KeyboardInput { event, .. }
+                            if event.state == ElementState::Pressed && !used_by_gui =>
+                        {
+                            if let PhysicalKey::Code(code) = event.physical_key {
+                                // Bindings take precedence, so any key can be rebound
+                                if let Some(action) = state.key_bindings.action(code, modifiers) {
+                                    state.apply_action(action);
+                                    return;
+                                }
+
+                                match code {
+                                    KeyCode::Escape => elwt.exit(),
+                                    KeyCode::F1 => state.toggle_gui(),
+                                    KeyCode::F11 => state.toggle_fullscreen(),
+                                    KeyCode::Home => {
+                                        state.update_view(|view, _| view.reset_camera());
+                                    }
+                                    KeyCode::KeyB => {
+                                        state.update_view(|view, _| view.toggle_sampling());
+                                    }
+                                    KeyCode::Digit1 => {
+                                        state.update_brush(|brush| brush.tool = Tool::Deposit);
+                                    }
+                                    KeyCode::Digit2 => {
+                                        state.update_brush(|brush| brush.tool = Tool::Erase);
+                                    }
+                                    KeyCode::Digit3 => {
+                                        state.update_brush(|brush| brush.tool = Tool::Attractor);
+                                    }
+                                    KeyCode::Digit4 => {
+                                        state.update_brush(|brush| brush.tool = Tool::Repeller);
                                     }
+                                    KeyCode::BracketLeft => {
+                                        state.update_brush(|brush| brush.scale_radius(0.8));
+                                    }
+                                    KeyCode::BracketRight => {
+                                        state.update_brush(|brush| brush.scale_radius(1.25));
+                                    }
+                                    KeyCode::Minus => {
+                                        state.update_brush(|brush| brush.adjust_strength(-0.05));
+                                    }
+                                    KeyCode::Equal => {
+                                        state.update_brush(|brush| brush.adjust_strength(0.05));
+                                    }
+                                    KeyCode::KeyC => {
+                                        if let Err(e) = state.save_checkpoint() {
+                                            eprintln!("Could not save checkpoint: {}", e);
+                                        }
+                                    }
+                                    KeyCode::Backspace => {
+                                        if let Err(e) = state.restore_checkpoint() {
+                                            eprintln!("Could not restore checkpoint: {}", e);
+                                        }
+                                    }
+                                    _ => (),
                                 }
                             }
-                            _ => (),
                         }
+                        _ => (),
                     }
                 }
                 Event::LoopExiting => {
@@ -363,4 +674,6 @@ pub async fn run() {
         .unwrap_or_else(|e| {
             eprintln!("An error occurred: {}", e);
         });
+
+    Ok(())
 }
@@ -1,6 +1,184 @@
-use physarum::run;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use physarum::{
+    backend::Backend,
+    capture::{FrameSink, FrameSize, PngSequence},
+    checkpoint::Checkpoint,
+    config, controls,
+    cpu::CpuSimulation,
+    device::Device,
+    environment,
+    parameters::Parameters,
+    presets, run,
+    video::{GifWriter, Y4mWriter},
+    Simulation, ViewerOptions,
+};
+
+mod cli;
+
+use cli::{BackendKind, Cli, Command, FrameFormat, PresetsCommand};
 
 #[tokio::main]
 async fn main() {
-    run().await;
+    let cli = Cli::parse_valid();
+
+    if let Err(e) = execute(cli).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn execute(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let preset_dir = cli.preset_dir.or_else(presets::user_dir);
+
+    match cli.command {
+        Command::View {
+            simulation,
+            resample_grid,
+            brush_radius,
+            brush_strength,
+            keys,
+            hide_gui,
+        } => {
+            let (params, checkpoint) = simulation.load(preset_dir.as_deref())?;
+            let key_bindings = keys.map(controls::load).transpose()?.unwrap_or_default();
+
+            run(ViewerOptions::builder()
+                .params(params)
+                .checkpoint(checkpoint)
+                .output(simulation.output)
+                .resample_grid(resample_grid)
+                .brush_radius(brush_radius)
+                .brush_strength(brush_strength)
+                .key_bindings(key_bindings)
+                .show_gui(!hide_gui)
+                .preset_dir(preset_dir)
+                .build())
+            .await?;
+        }
+        Command::Render {
+            simulation,
+            ticks,
+            every,
+            format,
+            size,
+            fps,
+            stdout,
+            checkpoint,
+            backend,
+        } => {
+            let (params, resume_from) = simulation.load(preset_dir.as_deref())?;
+            let mut backend = create_backend(backend, params, resume_from).await?;
+
+            fs::create_dir_all(&simulation.output)?;
+            config::save(backend.params(), simulation.output.join("parameters.toml"))?;
+
+            let mut frames = create_frame_sink(format, &simulation.output, size, fps, stdout)?;
+            let every = every.unwrap_or(ticks);
+
+            for tick in 1..=ticks {
+                backend.step();
+
+                if tick % every == 0 {
+                    frames
+                        .write_frame(&backend.params().shader_parameters, &backend.trail_map())?;
+                }
+            }
+
+            frames.finish()?;
+
+            if checkpoint {
+                Checkpoint::capture(backend.as_ref())
+                    .save(simulation.output.join("physarum.checkpoint"))?;
+            }
+        }
+        Command::Bench {
+            simulation,
+            ticks,
+            backend,
+        } => {
+            let (params, resume_from) = simulation.load(preset_dir.as_deref())?;
+            let mut backend = create_backend(backend, params, resume_from).await?;
+
+            let start = Instant::now();
+            for _ in 0..ticks {
+                backend.step();
+            }
+            // Reading the trail map back waits for all ticks to finish
+            backend.trail_map();
+            let elapsed = start.elapsed();
+
+            println!(
+                "{} ticks in {:.2?} ({:.1} ticks per second)",
+                ticks,
+                elapsed,
+                f64::from(ticks) / elapsed.as_secs_f64()
+            );
+        }
+        Command::Presets {
+            command: PresetsCommand::List,
+        } => {
+            for preset in presets::list(preset_dir.as_deref())? {
+                println!("{}", preset);
+            }
+        }
+        Command::Presets {
+            command: PresetsCommand::Save { name, simulation },
+        } => {
+            let (params, _) = simulation.load(preset_dir.as_deref())?;
+            let preset_dir = preset_dir.ok_or(presets::Error::NoUserDirectory)?;
+
+            let path = presets::save(&params, &name, &preset_dir)?;
+            println!("Saved preset '{}' to {}", name, path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn create_frame_sink(
+    format: FrameFormat,
+    output: &Path,
+    size: Option<FrameSize>,
+    fps: u32,
+    stdout: bool,
+) -> io::Result<Box<dyn FrameSink>> {
+    // Streams go to stdout if asked to, into a file in the output directory otherwise
+    let open = |file_name: &str| -> io::Result<BufWriter<Box<dyn Write>>> {
+        let writer: Box<dyn Write> = if stdout {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(File::create(output.join(file_name))?)
+        };
+        Ok(BufWriter::new(writer))
+    };
+
+    Ok(match format {
+        FrameFormat::Png => Box::new(PngSequence::new(output, size)),
+        FrameFormat::Y4m => Box::new(Y4mWriter::new(open("physarum.y4m")?, size, fps)),
+        FrameFormat::Gif => Box::new(GifWriter::new(open("physarum.gif")?, size, fps)),
+    })
+}
+
+async fn create_backend(
+    kind: BackendKind,
+    params: Parameters,
+    checkpoint: Option<Checkpoint>,
+) -> Result<Box<dyn Backend>, environment::Error> {
+    Ok(match (kind, checkpoint) {
+        (BackendKind::Gpu, None) => Box::new(Simulation::headless(params).await?),
+        (BackendKind::Gpu, Some(checkpoint)) => Box::new(Simulation::from_checkpoint(
+            Device::headless().await,
+            &checkpoint,
+        )?),
+        (BackendKind::Cpu, None) => Box::new(CpuSimulation::new(params)?),
+        (BackendKind::Cpu, Some(checkpoint)) => {
+            Box::new(CpuSimulation::from_checkpoint(checkpoint)?)
+        }
+    })
 }
@@ -1,30 +1,462 @@
+use std::{fmt, path::PathBuf};
+
+use serde::{
+    de::{
+        value::MapAccessDeserializer, DeserializeSeed, Error as _, IntoDeserializer, MapAccess,
+        Visitor,
+    },
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use smart_default::SmartDefault;
 use typed_builder::TypedBuilder;
 
-#[derive(Debug, Clone, Copy, PartialEq, TypedBuilder)]
+/// Canvas size used when none is given, e.g. by a config file that leaves it out.
+pub const DEFAULT_CANVAS_WIDTH: u32 = 1400;
+pub const DEFAULT_CANVAS_HEIGHT: u32 = 1400;
+
+/// Most species a simulation can have. Must match what is in the shader code.
+pub const MAX_SPECIES: usize = 4;
+
+/// Furthest a diffusion kernel may reach. Every pixel reads `(2 * radius + 1)²` others each tick,
+/// so much wider kernels stall the GPU for long enough to be reset.
+pub const MAX_KERNEL_RADIUS: u32 = 16;
+
+#[derive(Debug, Clone, PartialEq, TypedBuilder, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Parameters {
     /// Number of ticks of the simulation to target per second.
     #[builder(default = 60.0)]
+    #[serde(deserialize_with = "deserialize_ticks_per_second")]
     pub target_ticks_per_second: f32,
 
     /// Number of agents the buffer is initialized with
     #[builder(default = 500_000)]
     pub number_of_agents: u32,
 
+    /// Master seed of a run. It places the agents and seeds their random number generators, which
+    /// drive the random turns in the shaders. A fresh one is drawn on every run when unset.
+    #[builder(default)]
+    pub seed: Option<u64>,
+
     #[builder(default)]
     pub initial_conditions: InitialConditions,
 
+    /// Number of ticks simulated so far. Kept on the CPU only, as no shader reads it.
+    #[builder(default)]
+    #[serde(skip)]
+    pub tick: u32,
+
+    #[serde(deserialize_with = "deserialize_shader_parameters")]
     pub shader_parameters: ShaderParameters,
+
+    /// Species after the first, which follows the agent fields of `shader_parameters`. Only the
+    /// first `number_of_species - 1` are used.
+    #[builder(default)]
+    #[serde(
+        serialize_with = "serialize_species",
+        deserialize_with = "deserialize_species"
+    )]
+    pub species: [SpeciesParameters; MAX_SPECIES - 1],
+
+    /// `attraction[a][b]` weighs the trail of species `b` when species `a` senses. Negative values
+    /// repel. By default every species follows its own trail only.
+    #[builder(default = identity_matrix())]
+    #[serde(
+        serialize_with = "serialize_attraction",
+        deserialize_with = "deserialize_attraction"
+    )]
+    pub attraction: [[f32; MAX_SPECIES]; MAX_SPECIES],
+
+    /// How the trail spreads every tick. Fixed for the lifetime of a simulation.
+    #[builder(default)]
+    pub diffusion_kernel: DiffusionKernel,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::builder()
+            .shader_parameters(ShaderParameters::default())
+            .build()
+    }
+}
+
+impl Parameters {
+    /// Settles what is left open before a run starts: draws a seed unless there already is one,
+    /// and keeps the number of species within what the shader supports.
+    ///
+    /// Every run records its seed this way, so it can be reproduced.
+    pub fn prepare(&mut self) {
+        // TOML integers are signed, so keep drawn seeds within what a config file can hold
+        self.seed.get_or_insert_with(|| rand::random::<u64>() >> 1);
+
+        // Config files and the command line reject other counts, so only parameters built in code
+        // can get here out of range
+        let ctx = &mut self.shader_parameters;
+        ctx.number_of_species = ctx.number_of_species.clamp(1, MAX_SPECIES as u32);
+    }
+
+    /// Parameters of every species as the shader reads them.
+    pub fn species_table(&self) -> [SpeciesContext; MAX_SPECIES] {
+        std::array::from_fn(|i| {
+            let params = match i {
+                0 => SpeciesParameters::from(&self.shader_parameters),
+                _ => self.species[i - 1],
+            };
+
+            SpeciesContext {
+                params,
+                attraction: self.attraction[i],
+            }
+        })
+    }
+}
+
+// Old configs turning bouncing off would otherwise silently pick up the default boundary mode
+fn deserialize_shader_parameters<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ShaderParameters, D::Error> {
+    deserializer.deserialize_struct("ShaderParameters", &[], ShaderParametersVisitor)
+}
+
+/// Reads shader parameters as config files written before boundary modes may still list them.
+struct ShaderParametersVisitor;
+
+impl<'de> Visitor<'de> for ShaderParametersVisitor {
+    type Value = ShaderParameters;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "shader parameters")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ShaderParameters, A::Error> {
+        let mut bounce = None;
+        let mut shader_parameters =
+            ShaderParameters::deserialize(MapAccessDeserializer::new(WithoutBounce {
+                map,
+                bounce: &mut bounce,
+            }))?;
+
+        match bounce {
+            None => {}
+            Some(_) if shader_parameters.boundary_mode != BoundaryMode::default() => {
+                return Err(A::Error::custom(
+                    "bool_enable_agent_bounce is replaced by boundary_mode, remove it",
+                ));
+            }
+            Some(0) => shader_parameters.boundary_mode = BoundaryMode::Absorb,
+            Some(_) => shader_parameters.boundary_mode = BoundaryMode::Reflect,
+        }
+
+        Ok(shader_parameters)
+    }
+}
+
+/// Passes on every entry but `bool_enable_agent_bounce`, which agents used to bounce off the edges
+/// with, or walk off the canvas without.
+struct WithoutBounce<'a, A> {
+    map: A,
+    bounce: &'a mut Option<u32>,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for WithoutBounce<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        while let Some(FieldName(key)) = self.map.next_key()? {
+            if key == "bool_enable_agent_bounce" {
+                *self.bounce = Some(self.map.next_value()?);
+                continue;
+            }
+
+            return seed
+                .deserialize(IntoDeserializer::<A::Error>::into_deserializer(key))
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+}
+
+/// Name of a struct field, which RON only hands out as a `str`, not as a `String`.
+struct FieldName(String);
+
+impl<'de> Deserialize<'de> for FieldName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldNameVisitor;
+
+        impl Visitor<'_> for FieldNameVisitor {
+            type Value = FieldName;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a field name")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<FieldName, E> {
+                Ok(FieldName(name.to_string()))
+            }
+        }
+
+        deserializer.deserialize_str(FieldNameVisitor)
+    }
+}
+
+fn deserialize_ticks_per_second<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f32, D::Error> {
+    let ticks_per_second = f32::deserialize(deserializer)?;
+    if !(ticks_per_second.is_finite() && ticks_per_second > 0.0) {
+        return Err(D::Error::custom(
+            "target_ticks_per_second must be a positive number",
+        ));
+    }
+
+    Ok(ticks_per_second)
+}
+
+fn deserialize_number_of_species<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u32, D::Error> {
+    let number_of_species = u32::deserialize(deserializer)?;
+    if !(1..=MAX_SPECIES as u32).contains(&number_of_species) {
+        return Err(D::Error::custom(format!(
+            "number_of_species must be between 1 and {}",
+            MAX_SPECIES
+        )));
+    }
+
+    Ok(number_of_species)
+}
+
+fn identity_matrix() -> [[f32; MAX_SPECIES]; MAX_SPECIES] {
+    std::array::from_fn(|a| std::array::from_fn(|b| if a == b { 1.0 } else { 0.0 }))
+}
+
+// Written as lists rather than fixed size arrays, which formats like RON tell apart from lists and
+// the deserializers below would not read back
+fn serialize_species<S: Serializer>(
+    species: &[SpeciesParameters; MAX_SPECIES - 1],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(species)
+}
+
+fn serialize_attraction<S: Serializer>(
+    attraction: &[[f32; MAX_SPECIES]; MAX_SPECIES],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(attraction.iter().map(|row| row.as_slice()))
+}
+
+// Config files only need to list the species they use, the rest keep their defaults
+fn deserialize_species<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<[SpeciesParameters; MAX_SPECIES - 1], D::Error> {
+    let listed = Vec::<SpeciesParameters>::deserialize(deserializer)?;
+    if listed.len() > MAX_SPECIES - 1 {
+        return Err(D::Error::invalid_length(
+            listed.len(),
+            &"at most one entry per species after the first",
+        ));
+    }
+
+    let mut species = <[SpeciesParameters; MAX_SPECIES - 1]>::default();
+    species[..listed.len()].copy_from_slice(&listed);
+    Ok(species)
+}
+
+fn deserialize_attraction<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<[[f32; MAX_SPECIES]; MAX_SPECIES], D::Error> {
+    let listed = Vec::<Vec<f32>>::deserialize(deserializer)?;
+    if listed.len() > MAX_SPECIES || listed.iter().any(|row| row.len() > MAX_SPECIES) {
+        return Err(D::Error::custom(format!(
+            "attraction can be at most {}x{}",
+            MAX_SPECIES, MAX_SPECIES
+        )));
+    }
+
+    let mut attraction = identity_matrix();
+    for (row, listed_row) in attraction.iter_mut().zip(&listed) {
+        row[..listed_row.len()].copy_from_slice(listed_row);
+    }
+    Ok(attraction)
+}
+
+/// Weights diffusion averages the neighbourhood of a pixel with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffusionKernel {
+    /// Plain mean of the `(2 * radius + 1)²` pixels around.
+    Box {
+        #[serde(
+            default = "default_box_radius",
+            deserialize_with = "deserialize_kernel_radius"
+        )]
+        radius: u32,
+    },
+    /// Gaussian blur, applied as two passes over rows and then columns. Reaches out to `3 * sigma`.
+    Gaussian {
+        #[serde(deserialize_with = "deserialize_kernel_sigma")]
+        sigma: f32,
+    },
+    /// Any one-dimensional kernel of odd length, applied to rows and then to columns.
+    Separable {
+        #[serde(deserialize_with = "deserialize_kernel_row")]
+        weights: Vec<f32>,
+    },
+    /// Any square kernel of odd size, listed row by row.
+    Matrix {
+        #[serde(deserialize_with = "deserialize_kernel_matrix")]
+        weights: Vec<Vec<f32>>,
+    },
+}
+
+impl Default for DiffusionKernel {
+    fn default() -> Self {
+        Self::Box {
+            radius: default_box_radius(),
+        }
+    }
+}
+
+impl DiffusionKernel {
+    /// Whether the kernel is applied as a pass over rows followed by one over columns.
+    pub fn is_separable(&self) -> bool {
+        matches!(self, Self::Gaussian { .. } | Self::Separable { .. })
+    }
+
+    /// How far the kernel reaches from the pixel in its centre.
+    pub fn radius(&self) -> u32 {
+        match self {
+            Self::Box { radius } => (*radius).min(MAX_KERNEL_RADIUS),
+            Self::Gaussian { sigma } => {
+                ((3.0 * sigma).ceil().max(0.0) as u32).min(MAX_KERNEL_RADIUS)
+            }
+            Self::Separable { weights } => weights.len() as u32 / 2,
+            Self::Matrix { weights } => weights.len() as u32 / 2,
+        }
+    }
+
+    /// The weights row by row, or the single row of a separable kernel.
+    pub fn weights(&self) -> Vec<f32> {
+        let size = 2 * self.radius() as usize + 1;
+
+        match self {
+            Self::Box { .. } => vec![1.0 / (size * size) as f32; size * size],
+            Self::Gaussian { sigma } => {
+                let radius = self.radius() as f32;
+                let weights: Vec<f32> = (0..size)
+                    .map(|i| (-(i as f32 - radius).powi(2) / (2.0 * sigma * sigma)).exp())
+                    .collect();
+                let total: f32 = weights.iter().sum();
+                weights.iter().map(|w| w / total).collect()
+            }
+            Self::Separable { weights } => weights.clone(),
+            Self::Matrix { weights } => (0..size)
+                .flat_map(|j| (0..size).map(move |i| (j, i)))
+                .map(|(j, i)| weights.get(j).and_then(|row| row.get(i)).copied())
+                .map(|weight| weight.unwrap_or(0.0))
+                .collect(),
+        }
+    }
+}
+
+fn default_box_radius() -> u32 {
+    1
+}
+
+fn deserialize_kernel_radius<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let radius = u32::deserialize(deserializer)?;
+    if radius > MAX_KERNEL_RADIUS {
+        return Err(D::Error::custom(format!(
+            "kernel radius can be at most {}",
+            MAX_KERNEL_RADIUS
+        )));
+    }
+
+    Ok(radius)
+}
+
+fn deserialize_kernel_sigma<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    // Zero or NaN would make every weight NaN, and with it the whole trail map
+    let sigma = f32::deserialize(deserializer)?;
+    let max_sigma = MAX_KERNEL_RADIUS as f32 / 3.0;
+    if !(sigma > 0.0 && sigma <= max_sigma) {
+        return Err(D::Error::custom(format!(
+            "sigma must be above 0 and at most {:.2}",
+            max_sigma
+        )));
+    }
+
+    Ok(sigma)
+}
+
+fn deserialize_kernel_row<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<f32>, D::Error> {
+    let weights = Vec::<f32>::deserialize(deserializer)?;
+    if weights.len() % 2 == 0 {
+        return Err(D::Error::invalid_length(
+            weights.len(),
+            &"an odd number of weights",
+        ));
+    }
+    if weights.len() > 2 * MAX_KERNEL_RADIUS as usize + 1 {
+        return Err(D::Error::custom(format!(
+            "a kernel row can have at most {} weights",
+            2 * MAX_KERNEL_RADIUS + 1
+        )));
+    }
+
+    Ok(weights)
+}
+
+fn deserialize_kernel_matrix<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<f32>>, D::Error> {
+    let weights = Vec::<Vec<f32>>::deserialize(deserializer)?;
+    if weights.len() % 2 == 0 || weights.iter().any(|row| row.len() != weights.len()) {
+        return Err(D::Error::custom(
+            "kernel weights must be a square matrix of odd size",
+        ));
+    }
+    if weights.len() > 2 * MAX_KERNEL_RADIUS as usize + 1 {
+        return Err(D::Error::custom(format!(
+            "kernel weights can be at most {0}x{0}",
+            2 * MAX_KERNEL_RADIUS + 1
+        )));
+    }
+
+    Ok(weights)
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, TypedBuilder, bytemuck::Zeroable, bytemuck::NoUninit)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    TypedBuilder,
+    Serialize,
+    Deserialize,
+    bytemuck::Zeroable,
+    bytemuck::NoUninit,
+)]
+#[serde(default)]
 pub struct ShaderParameters {
     #[builder(default = 1.0)]
     pub agent_speed: f32,
 
-    #[builder(default = 1)]
-    pub bool_enable_agent_bounce: u32,
+    /// What happens to agents at the edges of the canvas.
+    #[builder(default)]
+    pub boundary_mode: BoundaryMode,
 
     #[builder(default = 1)]
     pub bool_enable_agent_deposit: u32,
@@ -94,9 +526,36 @@ pub struct ShaderParameters {
 
     #[builder(default = 33.8)]
     pub sensor_distance: f32,
+
+    /// Stimulus food adds to every trail layer each tick, scaled by the amount of food.
+    #[builder(default = 0.1)]
+    pub food_deposit_strength: f32,
+
+    /// Number of species the agents are split into, each leaving a trail of its own.
+    #[builder(default = 1)]
+    #[serde(deserialize_with = "deserialize_number_of_species")]
+    pub number_of_species: u32,
+
+    /// What diffusion takes for the pixels beyond the edges of the canvas.
+    #[builder(default)]
+    pub diffusion_edge_mode: DiffusionEdgeMode,
+}
+
+impl Default for ShaderParameters {
+    fn default() -> Self {
+        Self::builder()
+            .canvas_width(DEFAULT_CANVAS_WIDTH)
+            .canvas_height(DEFAULT_CANVAS_HEIGHT)
+            .build()
+    }
 }
 
 impl ShaderParameters {
+    /// Length of the trail map, which holds one canvas sized layer per species.
+    pub fn trail_map_len(&self) -> usize {
+        self.canvas_width as usize * self.canvas_height as usize * self.number_of_species as usize
+    }
+
     pub fn randomize(&mut self) {
         use rand::Rng as _;
         let mut rng = rand::thread_rng();
@@ -112,7 +571,51 @@ impl ShaderParameters {
     }
 }
 
-#[derive(Debug, Clone, Copy, SmartDefault, PartialEq)]
+/// The agent parameters that can differ between species.
+#[repr(C)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Serialize, Deserialize, bytemuck::Zeroable, bytemuck::NoUninit,
+)]
+#[serde(default)]
+pub struct SpeciesParameters {
+    pub agent_speed: f32,
+    pub sensor_angle_degrees: f32,
+    pub sensor_distance: f32,
+    pub max_turn_angle_degrees: f32,
+    pub max_rand_turn_angle_degrees: f32,
+    pub deposit_strength: f32,
+}
+
+impl Default for SpeciesParameters {
+    fn default() -> Self {
+        Self::from(&ShaderParameters::default())
+    }
+}
+
+impl From<&ShaderParameters> for SpeciesParameters {
+    fn from(ctx: &ShaderParameters) -> Self {
+        Self {
+            agent_speed: ctx.agent_speed,
+            sensor_angle_degrees: ctx.sensor_angle_degrees,
+            sensor_distance: ctx.sensor_distance,
+            max_turn_angle_degrees: ctx.max_turn_angle_degrees,
+            max_rand_turn_angle_degrees: ctx.max_rand_turn_angle_degrees,
+            deposit_strength: ctx.deposit_strength,
+        }
+    }
+}
+
+/// Entry of the species table in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Zeroable, bytemuck::NoUninit)]
+pub struct SpeciesContext {
+    pub params: SpeciesParameters,
+    /// How much this species is drawn to the trail of every species.
+    pub attraction: [f32; MAX_SPECIES],
+}
+
+#[derive(Debug, Clone, SmartDefault, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InitialConditions {
     /// Radius of circle in which agents are initially distributed
     #[default = 500.0]
@@ -120,12 +623,145 @@ pub struct InitialConditions {
 
     /// Initial agent direction
     pub initial_heading: InitialHeading,
+
+    /// Fixed food sources, in canvas pixels
+    pub food: Vec<FoodSource>,
+
+    /// Walls agents cannot pass, in canvas pixels
+    pub obstacles: Vec<Obstacle>,
+}
+
+/// Where food lies on the canvas.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FoodSource {
+    Point {
+        x: f32,
+        y: f32,
+    },
+    Disc {
+        x: f32,
+        y: f32,
+        radius: f32,
+    },
+    /// Greyscale image stretched over the canvas; brighter pixels hold more food.
+    Image {
+        path: PathBuf,
+    },
+    /// CSV file of `x,y` or `x,y,radius` rows, using `radius` for rows without one.
+    Csv {
+        path: PathBuf,
+        #[serde(default)]
+        radius: f32,
+    },
+}
+
+/// Part of the canvas walled off from the agents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Obstacle {
+    /// Spans `width` to the right and `height` down from its top left corner `(x, y)`.
+    Rectangle {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Circle {
+        x: f32,
+        y: f32,
+        radius: f32,
+    },
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+    /// Image stretched over the canvas, e.g. a maze; dark pixels are walls, or bright ones when
+    /// `invert` is set.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        invert: bool,
+    },
+}
+
+/// Topology of the canvas edges. Sensing and depositing follow it too, so agents on a toroidal
+/// canvas sense across the edges.
+#[repr(u32)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    bytemuck::Zeroable,
+    bytemuck::NoUninit,
+)]
+// Written in snake case like the other enums in config files, but still read as first written
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Leaving on one side enters on the opposite one.
+    #[serde(alias = "Wrap")]
+    Wrap = 0,
+    /// Agents bounce back off the edges.
+    #[default]
+    #[serde(alias = "Reflect")]
+    Reflect = 1,
+    /// Agents that leave the canvas are gone for good.
+    #[serde(alias = "Absorb")]
+    Absorb = 2,
+    /// Agents that leave the canvas reappear anywhere on it, heading anywhere.
+    #[serde(alias = "RespawnRandom")]
+    RespawnRandom = 3,
+    /// Agents that leave the canvas reappear where they started, heading anywhere.
+    #[serde(alias = "RespawnInitial")]
+    RespawnInitial = 4,
+}
+
+/// How the diffusion kernel treats neighbours beyond the edges of the canvas.
+#[repr(u32)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    bytemuck::Zeroable,
+    bytemuck::NoUninit,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffusionEdgeMode {
+    /// Beyond the edges is empty, which darkens the border.
+    #[default]
+    #[serde(alias = "Zero")]
+    Zero = 0,
+    /// Beyond the edges repeats the edge pixels.
+    #[serde(alias = "Clamp")]
+    Clamp = 1,
+    /// Beyond one edge continues on the opposite one.
+    #[serde(alias = "Wrap")]
+    Wrap = 2,
+    /// Beyond the edges mirrors the pixels inside, without repeating the edge pixels.
+    #[serde(alias = "Mirror")]
+    Mirror = 3,
+    /// Beyond the edges is left out, and so are walls, averaging only what remains.
+    #[serde(alias = "Renormalize")]
+    Renormalize = 4,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InitialHeading {
+    #[serde(alias = "Inward")]
     Inward,
+    #[serde(alias = "Outward")]
     Outward,
     #[default]
+    #[serde(alias = "Random")]
     Random,
 }
@@ -1,17 +1,17 @@
 use crate::resources::Resources;
 
 pub struct Pipelines {
+    pub shader: wgpu::ShaderModule,
+    pub pipeline_layout: wgpu::PipelineLayout,
     pub agent_sense_move_deposit: wgpu::ComputePipeline,
+    pub diffuse_rows: wgpu::ComputePipeline,
     pub diffuse_and_decay: wgpu::ComputePipeline,
-    pub render_pipeline: wgpu::RenderPipeline,
+    pub apply_deposits: wgpu::ComputePipeline,
+    pub paint: wgpu::ComputePipeline,
 }
 
 impl Pipelines {
-    pub fn new(
-        device: &wgpu::Device,
-        surface_format: wgpu::TextureFormat,
-        resources: &Resources,
-    ) -> Self {
+    pub fn new(device: &wgpu::Device, resources: &Resources) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader"),
             source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
@@ -35,6 +35,13 @@ impl Pipelines {
                 entry_point: "agent_sense_move_deposit",
             });
 
+        let diffuse_rows = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("diffuse-rows-compute-pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "diffuse_rows",
+        });
+
         let diffuse_and_decay = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label: Some("trail-layer-compute-pipeline"),
             layout: Some(&pipeline_layout),
@@ -42,19 +49,65 @@ impl Pipelines {
             entry_point: "diffuse_and_decay",
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let apply_deposits = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("apply-deposits-compute-pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "apply_deposits",
+        });
+
+        let paint = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("paint-compute-pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "paint",
+        });
+
+        Self {
+            shader,
+            pipeline_layout,
+            agent_sense_move_deposit,
+            diffuse_rows,
+            diffuse_and_decay,
+            apply_deposits,
+            paint,
+        }
+    }
+
+    /// The render pipeline depends on the format of the target it draws into and on how the trail
+    /// map is shown there, so it is only created by whoever owns that target (e.g. the window
+    /// surface). The view is bound after the simulation resources.
+    pub fn create_render_pipeline(
+        &self,
+        device: &wgpu::Device,
+        resources: &Resources,
+        view_bind_group_layout: &wgpu::BindGroupLayout,
+        target_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("render-pipeline-layout"),
+            bind_group_layouts: &[
+                &resources.shader_context.bind_group_layout,
+                &resources.data_layer.bind_group_layout,
+                &resources.trail_layer.bind_group_layout,
+                view_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("render-pipeline"),
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: &self.shader,
                 entry_point: "vertex_main",
                 buffers: &[],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: &self.shader,
                 entry_point: "fragment_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
+                    format: target_format,
                     blend: Some(wgpu::BlendState {
                         color: wgpu::BlendComponent::REPLACE,
                         alpha: wgpu::BlendComponent::REPLACE,
@@ -76,12 +129,6 @@ impl Pipelines {
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-        });
-
-        Self {
-            agent_sense_move_deposit,
-            diffuse_and_decay,
-            render_pipeline,
-        }
+        })
     }
 }
@@ -0,0 +1,297 @@
+//! Named parameter sets, to come back to good settings instead of hunting for them again.
+//!
+//! A few presets are bundled with the crate. More can be saved to a user preset directory, where
+//! they are plain config files and take precedence over bundled presets of the same name. Names
+//! are matched ignoring case, with spaces, hyphens and underscores all alike, so "Jones 2010" finds
+//! `jones-2010.toml`.
+
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{self, Format},
+    parameters::Parameters,
+};
+
+/// Presets that come with the crate, as TOML config files.
+const BUNDLED: &[(&str, &str)] = &[
+    ("cells", include_str!("../presets/cells.toml")),
+    ("coral", include_str!("../presets/coral.toml")),
+    ("jones-2010", include_str!("../presets/jones-2010.toml")),
+    ("veins", include_str!("../presets/veins.toml")),
+];
+
+#[derive(Debug)]
+pub enum Error {
+    Config(config::Error),
+    Unknown(String),
+    InvalidName(String),
+    /// Saving needs somewhere to save to.
+    NoUserDirectory,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Unknown(name) => write!(f, "no preset named '{}'", name),
+            Error::InvalidName(name) => write!(
+                f,
+                "'{}' is not a preset name (use letters, digits, spaces, '-' and '_')",
+                name
+            ),
+            Error::NoUserDirectory => write!(
+                f,
+                "cannot tell where to keep presets (set PHYSARUM_PRESETS or HOME)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<config::Error> for Error {
+    fn from(e: config::Error) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Config(config::Error::Io(e))
+    }
+}
+
+/// Where a preset comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Bundled,
+    User(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    pub name: String,
+    pub source: Source,
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Source::Bundled => write!(f, "{} (bundled)", self.name),
+            Source::User(path) => write!(f, "{} ({})", self.name, path.display()),
+        }
+    }
+}
+
+/// Directory user presets are kept in: `$PHYSARUM_PRESETS` if set, otherwise
+/// `physarum/presets` in `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn user_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("PHYSARUM_PRESETS") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("physarum").join("presets"))
+}
+
+/// Every preset there is, sorted by name. User presets hide bundled ones of the same name.
+pub fn list(user_dir: Option<&Path>) -> Result<Vec<Preset>, Error> {
+    let mut presets = match user_dir {
+        Some(dir) => user_presets(dir)?,
+        None => Vec::new(),
+    };
+
+    for (name, _) in BUNDLED {
+        if !presets.iter().any(|preset| same_name(&preset.name, name)) {
+            presets.push(Preset {
+                name: name.to_string(),
+                source: Source::Bundled,
+            });
+        }
+    }
+
+    presets.sort_by_key(|preset| preset.name.to_lowercase());
+    Ok(presets)
+}
+
+/// Parameters of the preset called `name`, looked up in `user_dir` first.
+pub fn load(name: &str, user_dir: Option<&Path>) -> Result<Parameters, Error> {
+    if let Some(dir) = user_dir {
+        let found = user_presets(dir)?
+            .into_iter()
+            .find(|preset| same_name(&preset.name, name));
+        if let Some(Preset {
+            source: Source::User(path),
+            ..
+        }) = found
+        {
+            return Ok(config::load(path)?);
+        }
+    }
+
+    let (_, toml) = BUNDLED
+        .iter()
+        .find(|(bundled, _)| same_name(bundled, name))
+        .ok_or_else(|| Error::Unknown(name.to_string()))?;
+    Ok(config::from_str(toml, Format::Toml)?)
+}
+
+/// Saves `params` as a preset called `name` in `user_dir`, replacing any preset saved under that
+/// name before. Returns the file written.
+pub fn save(params: &Parameters, name: &str, user_dir: &Path) -> Result<PathBuf, Error> {
+    let name = name.trim();
+    let valid = name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'));
+    if name.is_empty() || !valid {
+        return Err(Error::InvalidName(name.to_string()));
+    }
+
+    // Serialize first, so nothing on disk is touched for parameters a config file cannot hold
+    let contents = config::to_string(params, Format::Toml)?;
+
+    fs::create_dir_all(user_dir)?;
+    let path = user_dir.join(format!("{}.toml", name));
+
+    // Write next to the old preset and swap it in, so a failed save leaves the old one intact
+    let temporary = user_dir.join(format!(".{}.toml.tmp", name));
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, &path)?;
+
+    // Only then drop what it replaces in other formats, rather than shadowing one with the other
+    let saved = fs::canonicalize(&path)?;
+    for preset in user_presets(user_dir)? {
+        if let Source::User(other) = preset.source {
+            if same_name(&preset.name, name) && fs::canonicalize(&other)? != saved {
+                fs::remove_file(other)?;
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+/// Config files in `dir`, which need not exist yet.
+fn user_presets(dir: &Path) -> Result<Vec<Preset>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut presets = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || Format::from_path(&path).is_none() {
+            continue;
+        }
+
+        if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+            presets.push(Preset {
+                name: name.to_string(),
+                source: Source::User(path.clone()),
+            });
+        }
+    }
+
+    Ok(presets)
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    let normalize = |name: &str| -> String {
+        name.trim()
+            .chars()
+            .map(|c| match c {
+                ' ' | '_' => '-',
+                c => c.to_ascii_lowercase(),
+            })
+            .collect()
+    };
+    normalize(a) == normalize(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory of its own for every test, as tests run in parallel.
+    fn temporary_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("physarum-presets-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn bundled_presets_load() {
+        for (name, _) in BUNDLED {
+            if let Err(e) = load(name, None) {
+                panic!("bundled preset '{}' does not load: {}", name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn names_match_ignoring_case_and_separators() {
+        assert!(same_name("Jones 2010", "jones-2010"));
+        assert!(same_name("jones_2010", "JONES-2010"));
+        assert!(same_name(" veins ", "veins"));
+        assert!(!same_name("jones 2010", "jones2010"));
+        assert!(!same_name("cells", "coral"));
+
+        assert_eq!(
+            load("Jones 2010", None).unwrap(),
+            load("jones-2010", None).unwrap()
+        );
+        assert!(matches!(load("nope", None), Err(Error::Unknown(_))));
+    }
+
+    #[test]
+    fn user_presets_shadow_bundled_ones() {
+        let dir = temporary_dir("shadow");
+        let params = Parameters::builder()
+            .number_of_agents(1234)
+            .shader_parameters(Default::default())
+            .build();
+        let path = save(&params, "Veins", &dir).unwrap();
+
+        let presets = list(Some(&dir)).unwrap();
+        let veins: Vec<_> = presets
+            .iter()
+            .filter(|preset| same_name(&preset.name, "veins"))
+            .collect();
+        assert_eq!(veins.len(), 1);
+        assert_eq!(veins[0].source, Source::User(path));
+        assert_eq!(presets.len(), BUNDLED.len());
+
+        assert_eq!(load("veins", Some(&dir)).unwrap().number_of_agents, 1234);
+        assert_ne!(load("veins", None).unwrap().number_of_agents, 1234);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_save_keeps_the_old_preset() {
+        let dir = temporary_dir("failed-save");
+        let params = Parameters::default();
+        let path = save(&params, "mine", &dir).unwrap();
+
+        // TOML integers are signed, so this seed cannot be written
+        let unwritable = Parameters {
+            seed: Some(u64::MAX),
+            ..params.clone()
+        };
+        assert!(save(&unwritable, "mine", &dir).is_err());
+        assert!(save(&params, "no/such", &dir).is_err());
+
+        assert_eq!(config::load(&path).unwrap(), params);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
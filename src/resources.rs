@@ -1,6 +1,11 @@
 use wgpu::util::DeviceExt;
 
-use crate::{agent::{initial_agent_distribution, Agent}, parameters::{Parameters, ShaderParameters}};
+use crate::{
+    agent::{initial_agent_distribution, Agent},
+    brush::BrushStroke,
+    environment::Environment,
+    parameters::{Parameters, ShaderParameters},
+};
 
 pub struct Resource {
     pub buffer: wgpu::Buffer,
@@ -8,27 +13,117 @@ pub struct Resource {
     pub bind_group_layout: wgpu::BindGroupLayout,
 }
 
+/// The trail map twice over, so diffusion can read one copy while it writes the other.
+pub struct TrailLayers {
+    pub buffers: [wgpu::Buffer; 2],
+    /// `bind_groups[i]` binds `buffers[i]` as the trail map and the other buffer as the target of
+    /// diffusion.
+    pub bind_groups: [wgpu::BindGroup; 2],
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    /// Number of agents depositing on every pixel in the tick in progress, shared by both bind
+    /// groups.
+    pub deposits: wgpu::Buffer,
+    /// Trail map after the first of the two passes of a separable diffusion kernel.
+    pub diffused_rows: wgpu::Buffer,
+}
+
 pub struct Resources {
     pub shader_context: Resource,
+    /// Parameters of every species, bound next to the shader context.
+    pub species_table: wgpu::Buffer,
+    /// Radius, separability and weights of the diffusion kernel, bound next to the shader context.
+    pub diffusion_kernel: wgpu::Buffer,
+    /// Stroke the paint pass adds to the canvas, bound next to the shader context.
+    pub brush: wgpu::Buffer,
     pub data_layer: Resource,
-    pub trail_layer: Resource,
+    pub trail_layer: TrailLayers,
+    /// Food on the canvas, bound next to the trail map. Painted attractors add to it and painted
+    /// repellers take it below zero.
+    pub food_layer: wgpu::Buffer,
+    /// Obstacles on the canvas, bound next to the trail map.
+    pub mask_layer: wgpu::Buffer,
 }
 
 impl Resources {
-    pub fn new(device: &wgpu::Device, params: &Parameters) -> Self {
-        let shader_context = create_shader_context(device, params);
-        let data_layer = create_data_layer(device, params);
-        let trail_layer = create_trail_layer(device, params);
+    pub fn new(device: &wgpu::Device, params: &Parameters, environment: &Environment) -> Self {
+        let agents = initial_agent_distribution(params);
+
+        // Start with a black canvas
+        let trail_map: Vec<f32> = vec![0.0; params.shader_parameters.trail_map_len()];
+
+        Self::with_state(device, params, environment, &agents, &trail_map)
+    }
+
+    /// Creates the resources with the given agents and trail map instead of a fresh start, e.g. to
+    /// resume from a checkpoint.
+    pub fn with_state(
+        device: &wgpu::Device,
+        params: &Parameters,
+        environment: &Environment,
+        agents: &[Agent],
+        trail_map: &[f32],
+    ) -> Self {
+        let species_table = create_species_table(device, params);
+        let diffusion_kernel = create_diffusion_kernel(device, params);
+        let brush = create_brush(device);
+        let shader_context =
+            create_shader_context(device, params, &species_table, &diffusion_kernel, &brush);
+        let data_layer = create_data_layer(device, params, agents);
+        let food_layer = create_food_layer(device, environment);
+        let mask_layer = create_mask_layer(device, environment);
+        let trail_layer = create_trail_layer(device, params, trail_map, &food_layer, &mask_layer);
 
         Self {
             shader_context,
+            species_table,
+            diffusion_kernel,
+            brush,
             data_layer,
             trail_layer,
+            food_layer,
+            mask_layer,
         }
     }
 }
 
-fn create_shader_context(device: &wgpu::Device, params: &Parameters) -> Resource {
+fn create_species_table(device: &wgpu::Device, params: &Parameters) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("species-table"),
+        contents: bytemuck::cast_slice(&params.species_table()),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+fn create_diffusion_kernel(device: &wgpu::Device, params: &Parameters) -> wgpu::Buffer {
+    let kernel = &params.diffusion_kernel;
+
+    // Laid out as the DiffusionKernel struct in the shader
+    let mut contents = vec![kernel.radius(), u32::from(kernel.is_separable())];
+    contents.extend(kernel.weights().iter().map(|weight| weight.to_bits()));
+
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("diffusion-kernel"),
+        contents: bytemuck::cast_slice(&contents),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+fn create_brush(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("brush"),
+        size: std::mem::size_of::<BrushStroke>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_shader_context(
+    device: &wgpu::Device,
+    params: &Parameters,
+    species_table: &wgpu::Buffer,
+    diffusion_kernel: &wgpu::Buffer,
+    brush: &wgpu::Buffer,
+) -> Resource {
     let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("shader-context"),
         contents: bytemuck::cast_slice(&[params.shader_parameters]),
@@ -37,27 +132,73 @@ fn create_shader_context(device: &wgpu::Device, params: &Parameters) -> Resource
 
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("shader-context-bind-group-layout"),
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX_FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: wgpu::BufferSize::new(
-                    std::mem::size_of::<ShaderParameters>() as u64
-                ),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<ShaderParameters>() as u64,
+                    ),
+                },
+                count: None,
             },
-            count: None,
-        }],
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(species_table.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(diffusion_kernel.size()),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(brush.size()),
+                },
+                count: None,
+            },
+        ],
     });
 
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: Some("shader-context-bind-group"),
         layout: &bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffer.as_entire_binding(),
-        }],
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: species_table.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: diffusion_kernel.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: brush.as_entire_binding(),
+            },
+        ],
     });
 
     Resource {
@@ -67,13 +208,13 @@ fn create_shader_context(device: &wgpu::Device, params: &Parameters) -> Resource
     }
 }
 
-fn create_data_layer(device: &wgpu::Device, params: &Parameters) -> Resource {
-    let agents = initial_agent_distribution(params);
-
+fn create_data_layer(device: &wgpu::Device, params: &Parameters, agents: &[Agent]) -> Resource {
     let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("data-layer"),
-        contents: bytemuck::cast_slice(&agents),
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        contents: bytemuck::cast_slice(agents),
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
     });
 
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -108,50 +249,140 @@ fn create_data_layer(device: &wgpu::Device, params: &Parameters) -> Resource {
     }
 }
 
-fn create_trail_layer(device: &wgpu::Device, params: &Parameters) -> Resource {
-    let canvas_resolution =
-        params.shader_parameters.canvas_width * params.shader_parameters.canvas_height;
+fn create_food_layer(device: &wgpu::Device, environment: &Environment) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("food-layer"),
+        contents: bytemuck::cast_slice(&environment.food),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
 
-    // Start with a black canvas
-    let init: Vec<f32> = vec![0.0; canvas_resolution as usize];
+fn create_mask_layer(device: &wgpu::Device, environment: &Environment) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("mask-layer"),
+        contents: bytemuck::cast_slice(&environment.mask),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
 
-    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("trail-layer"),
-        contents: bytemuck::cast_slice(&init),
-        usage: wgpu::BufferUsages::STORAGE
-            | wgpu::BufferUsages::COPY_DST
-            | wgpu::BufferUsages::COPY_SRC,
+fn create_trail_layer(
+    device: &wgpu::Device,
+    params: &Parameters,
+    trail_map: &[f32],
+    food_layer: &wgpu::Buffer,
+    mask_layer: &wgpu::Buffer,
+) -> TrailLayers {
+    let buffers = ["trail-layer-0", "trail-layer-1"].map(|label| {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(trail_map),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        })
     });
 
+    // Rows blurred by the first pass of a separable kernel. Only read within the tick, so it does
+    // not need to start out as anything in particular
+    let diffused_rows = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("diffused-rows"),
+        size: std::mem::size_of_val(trail_map) as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    // Deposits are added to the trail map within the tick, so they always start out at zero
+    let deposits = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("deposits"),
+        size: (trail_map.len() * std::mem::size_of::<u32>()) as u64,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+
+    let trail_map_sized_binding = |binding, visibility| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(
+                (params.shader_parameters.trail_map_len() * std::mem::size_of::<f32>()) as u64,
+            ),
+        },
+        count: None,
+    };
+
     let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("trail-layer-bind-group-layout"),
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: false },
-                has_dynamic_offset: false,
-                min_binding_size: wgpu::BufferSize::new(
-                    (usize::try_from(canvas_resolution).unwrap() * std::mem::size_of::<f32>())
-                        as u64,
-                ),
+        entries: &[
+            trail_map_sized_binding(
+                0,
+                wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(food_layer.size()),
+                },
+                count: None,
             },
-            count: None,
-        }],
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(mask_layer.size()),
+                },
+                count: None,
+            },
+            trail_map_sized_binding(3, wgpu::ShaderStages::COMPUTE),
+            trail_map_sized_binding(4, wgpu::ShaderStages::COMPUTE),
+            trail_map_sized_binding(5, wgpu::ShaderStages::COMPUTE),
+        ],
     });
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("trail-layer-bind-group"),
-        layout: &bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffer.as_entire_binding(),
-        }],
+    let bind_groups = [0, 1].map(|i| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("trail-layer-bind-group-{}", i)),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffers[i].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: food_layer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: mask_layer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: buffers[1 - i].as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: deposits.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: diffused_rows.as_entire_binding(),
+                },
+            ],
+        })
     });
 
-    Resource {
-        buffer,
-        bind_group,
+    TrailLayers {
+        buffers,
+        bind_groups,
         bind_group_layout,
+        deposits,
+        diffused_rows,
     }
 }
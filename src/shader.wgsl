@@ -1,6 +1,6 @@
 struct ShaderParameters {
     agent_speed: f32,
-    bool_enable_agent_bounce: u32,
+    boundary_mode: u32,
     bool_enable_agent_deposit: u32,
     bool_enable_agent_rotate: u32,
     bool_enable_agent_rotate_left: u32,
@@ -23,23 +23,110 @@ struct ShaderParameters {
     high_density_speed_boost: f32,
     deposit_strength: f32,
     sensor_distance: f32,
+    food_deposit_strength: f32,
+    number_of_species: u32,
+    diffusion_edge_mode: u32,
+}
+
+// Must match MAX_SPECIES in parameters.rs
+const MAX_SPECIES: u32 = 4u;
+
+// Must match BoundaryMode in parameters.rs
+const BOUNDARY_WRAP: u32 = 0u;
+const BOUNDARY_REFLECT: u32 = 1u;
+const BOUNDARY_ABSORB: u32 = 2u;
+const BOUNDARY_RESPAWN_RANDOM: u32 = 3u;
+const BOUNDARY_RESPAWN_INITIAL: u32 = 4u;
+
+// Must match DiffusionEdgeMode in parameters.rs
+const DIFFUSION_EDGE_ZERO: u32 = 0u;
+const DIFFUSION_EDGE_CLAMP: u32 = 1u;
+const DIFFUSION_EDGE_WRAP: u32 = 2u;
+const DIFFUSION_EDGE_MIRROR: u32 = 3u;
+const DIFFUSION_EDGE_RENORMALIZE: u32 = 4u;
+
+// Must match Sampling in view.rs
+const SAMPLING_NEAREST: u32 = 0u;
+const SAMPLING_BILINEAR: u32 = 1u;
+
+struct Species {
+    agent_speed: f32,
+    sensor_angle_degrees: f32,
+    sensor_distance: f32,
+    max_turn_angle_degrees: f32,
+    max_rand_turn_angle_degrees: f32,
+    deposit_strength: f32,
+    // How much this species is drawn to the trail of every species (negative repels)
+    attraction: array<f32, MAX_SPECIES>,
+}
+
+struct DiffusionKernel {
+    radius: u32,
+    // Whether the weights are a single row, applied to rows and then to columns
+    separable: u32,
+    weights: array<f32>,
 }
 
 struct Agent {
     position: vec2<f32>,
     velocity: vec2<f32>,
+    initial_position: vec2<f32>,
+    rng_state: u32,
+    species: u32,
+}
+
+// Must match BrushStroke in brush.rs
+struct BrushStroke {
+    x: f32,
+    y: f32,
+    radius: f32,
+    trail_strength: f32,
+    food_strength: f32,
 }
 
 struct TrailMap {
     data: array<f32>,
 }
 
+// Must match ViewParameters in view.rs
+struct View {
+    surface_width: u32,
+    surface_height: u32,
+    zoom: f32,
+    sampling: u32,
+    translate: vec2<f32>,
+}
+
 @group(0) @binding(0)
 var<uniform> ctx: ShaderParameters;
+@group(0) @binding(1)
+var<storage, read> species_table: array<Species, MAX_SPECIES>;
+@group(0) @binding(2)
+var<storage, read> diffusion_kernel: DiffusionKernel;
+@group(0) @binding(3)
+var<uniform> brush: BrushStroke;
 @group(1) @binding(0)
 var<storage, read_write> agents_buffer: array<Agent>;
 @group(2) @binding(0)
 var<storage, read_write> trail_map: TrailMap;
+// Where diffusion writes the next trail map, so it never reads pixels it has already written
+@group(2) @binding(3)
+var<storage, read_write> next_trail_map: TrailMap;
+// Agents depositing on every pixel this tick, counted atomically so none get lost when agents
+// share a pixel. All agents on a layer are of one species and deposit the same, so the count is
+// all it takes, and it cannot overflow like a running sum of deposits could.
+@group(2) @binding(4)
+var<storage, read_write> deposits: array<atomic<u32>>;
+// Trail map after the first pass of a separable diffusion kernel
+@group(2) @binding(5)
+var<storage, read_write> diffused_rows: TrailMap;
+@group(3) @binding(0)
+var<uniform> view: View;
+@group(2) @binding(1)
+var<storage, read_write> food_layer: array<f32>;
+// Non-zero where an obstacle covers the canvas
+@group(2) @binding(2)
+var<storage, read> mask_layer: array<u32>;
 
 @vertex
 fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
@@ -64,16 +151,28 @@ fn vertex_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) v
 
 @fragment
 fn fragment_main(@builtin(position) coord: vec4<f32>) -> @location(0) vec4<f32> {
-    let x = u32(coord.x);
-    let y = u32(coord.y);
+    // Fit the canvas into the surface, keeping its aspect ratio, and letterbox the rest. The
+    // camera then zooms into it around the middle of the surface
+    let canvas = vec2(f32(ctx.canvas_width), f32(ctx.canvas_height));
+    let surface = vec2(f32(view.surface_width), f32(view.surface_height));
+    let scale = min(surface.x / canvas.x, surface.y / canvas.y) * view.zoom;
+    let position = (coord.xy - surface / 2.0) / scale + canvas / 2.0 - view.translate;
+
+    if any(position < vec2(0.0, 0.0)) || any(position >= canvas) {
+        return vec4(0.0, 0.0, 0.0, 1.0);
+    }
 
     if !bool(ctx.bool_enable_render_trail_map) {
         // Output a solid red color
         return vec4(1.0, 0.0, 0.0, 1.0);
     }
 
+    if ctx.number_of_species > 1u {
+        return vec4(mix_species(position), 1.0);
+    }
+
     // Sample trail map:
-    let v: f32 = trail_map.data[y * ctx.canvas_width + x];
+    let v: f32 = sample_trail_map(0u, position);
 
     if v > 1.0 {
         // Not supposed to happen
@@ -112,98 +211,163 @@ fn agent_sense_move_deposit(
     let agent_idx = global_invocation_index;
 
     // Handle overflow (e.g. 500_000 agents are not divisible by the number of threads actually created on the gpu)
-    if agent_idx >= ctx.number_of_active_agents {
+    if agent_idx >= ctx.number_of_active_agents || agent_idx >= arrayLength(&agents_buffer) {
         return;
     }
 
     // Fetch the agent data
     var agent = agents_buffer[agent_idx];
 
+    // Absorbed agents stay where they left the canvas
+    if ctx.boundary_mode == BOUNDARY_ABSORB && is_out_of_bounds(agent.position) {
+        return;
+    }
+    let species = species_table[agent.species];
+
     // SENSE: Get deposit values at the sensors
     let sensor_ahead_val: f32 = sense(agent, 0.0);
-    let sensor_ccw_val: f32 = sense(agent, ctx.sensor_angle_degrees);
-    let sensor_cw_val: f32 = sense(agent, -ctx.sensor_angle_degrees);
+    let sensor_ccw_val: f32 = sense(agent, species.sensor_angle_degrees);
+    let sensor_cw_val: f32 = sense(agent, -species.sensor_angle_degrees);
 
     // let rand_turn_strength = random_float_in_range(-1.0, 1.0, seed_from_agent(agent));
 
-    // Seed for randomness
-    let seed = seed_from_agent(agent);
+    // Every agent draws from its own random stream
+    var rng_state = agent.rng_state;
 
     // ROTATE: Update agent direction (rotate its velocity)
     if bool(ctx.bool_enable_agent_rotate) {
         if bool(ctx.bool_enable_agent_rotate_left) &&sensor_ccw_val > sensor_ahead_val && sensor_ahead_val > sensor_cw_val {
             // Turn counter-clockwise
             // agent.velocity = rotate_ccw(abs(rand_turn_strength) * ctx.max_turn_angle_degrees, agent.velocity);
-            agent.velocity = rotate_ccw(ctx.max_turn_angle_degrees, agent.velocity);
+            agent.velocity = rotate_ccw(species.max_turn_angle_degrees, agent.velocity);
         }
         if bool(ctx.bool_enable_agent_rotate_right) && sensor_cw_val > sensor_ahead_val && sensor_ahead_val > sensor_ccw_val {
             // Turn clockwise
             // agent.velocity = rotate_cw(abs(rand_turn_strength) * ctx.max_turn_angle_degrees, agent.velocity);
-            agent.velocity = rotate_cw(ctx.max_turn_angle_degrees, agent.velocity);
+            agent.velocity = rotate_cw(species.max_turn_angle_degrees, agent.velocity);
         }
         if bool(ctx.bool_enable_agent_rotate_right) && sensor_cw_val > sensor_ahead_val && sensor_ccw_val > sensor_ahead_val {
             // Rotate randomly left or right by RA.
 
-            let ra = rand_sign(seed) * ctx.max_turn_angle_degrees;
+            let ra = rand_sign(&rng_state) * species.max_turn_angle_degrees;
             agent.velocity = rotate_cw(ra, agent.velocity);
 
             // agent.velocity = rotate_cw(rand_turn_strength * ctx.max_rand_turn_angle_degrees, agent.velocity);
         }
     }
 
-    var speed = ctx.agent_speed;
+    var speed = species.agent_speed;
 
     // TWIST: If the deposit density is too great (too many agents in the same spot)
     // TODO: create toggle
     if bool(ctx.bool_enable_high_density_dispersion) {
-        if deposit_strength_at(agent.position) >= ctx.high_density_threshold {
+        if deposit_strength_at(agent.species, agent.position) >= ctx.high_density_threshold {
             // Add speed proportional to the speed boost param and the deposit strength at the current position
-            speed = speed + ctx.high_density_speed_boost * deposit_strength_at(agent.position);
+            speed = speed + ctx.high_density_speed_boost * deposit_strength_at(agent.species, agent.position);
 
             // Turn agent randomly
-            agent.velocity = rotate_cw(random_float_in_range(-ctx.max_rand_turn_angle_degrees, ctx.max_rand_turn_angle_degrees, seed), agent.velocity);
+            agent.velocity = rotate_cw(random_float_in_range(-species.max_rand_turn_angle_degrees, species.max_rand_turn_angle_degrees, &rng_state), agent.velocity);
         }
     }
 
-    // BOUNCE: Bounce on window borders
-    if bool(ctx.bool_enable_agent_bounce) {
-        // Bounce on left edge
-        if (agent.position.x < 0.0) {
-            agent.velocity.x = abs(agent.velocity.x);
+    // MOVE: Move agent forward, unless that runs it into a wall
+    let step = agent.velocity * speed;
+    if is_blocked(agent.position + step) && !is_blocked(agent.position) {
+        // Bounce off the wall along the axes that run into it, or turn back in a corner
+        let blocked_x = is_blocked(agent.position + vec2(step.x, 0.0));
+        let blocked_y = is_blocked(agent.position + vec2(0.0, step.y));
+        if blocked_x {
+            agent.velocity.x = -agent.velocity.x;
         }
-        // Bounce on right edge
-        if (agent.position.x > f32(ctx.canvas_width)) {
-            agent.velocity.x = -abs(agent.velocity.x);
+        if blocked_y {
+            agent.velocity.y = -agent.velocity.y;
         }
-        // Bounce on top edge
-        if (agent.position.y < 0.0) {
-            agent.velocity.y = abs(agent.velocity.y);
+        if !blocked_x && !blocked_y {
+            agent.velocity = -agent.velocity;
         }
-        // Bounce on bottom edge
-        if (agent.position.y > f32(ctx.canvas_height)) {
-            agent.velocity.y = -abs(agent.velocity.y);
+    } else {
+        agent.position += step;
+    }
+
+    // BOUNDARY: Handle agents that left the canvas
+    if is_out_of_bounds(agent.position) {
+        switch (ctx.boundary_mode) {
+            case BOUNDARY_WRAP: {
+                agent.position = vec2(
+                    wrap(agent.position.x, f32(ctx.canvas_width)),
+                    wrap(agent.position.y, f32(ctx.canvas_height))
+                );
+            }
+            case BOUNDARY_REFLECT: {
+                agent = reflect_off_edges(agent);
+            }
+            case BOUNDARY_RESPAWN_RANDOM: {
+                agent.position = vec2(
+                    random_float_in_range(0.0, f32(ctx.canvas_width - 1u), &rng_state),
+                    random_float_in_range(0.0, f32(ctx.canvas_height - 1u), &rng_state)
+                );
+                agent.velocity = random_heading(&rng_state);
+            }
+            case BOUNDARY_RESPAWN_INITIAL: {
+                agent.position = agent.initial_position;
+                agent.velocity = random_heading(&rng_state);
+            }
+            default: {}
         }
     }
 
-    // MOVE: Move agent forward
-    agent.position += agent.velocity * speed;
+    agent.rng_state = rng_state;
 
     // Update agent data in the buffer
     agents_buffer[agent_idx] = agent;
 
     // Calculate pixel position to color
-    let pos: vec2<f32> = agent.position;
+    let pixel_idx = pixel_index(agent.position);
 
-    if bool(ctx.bool_enable_agent_deposit) && !is_out_of_bounds(pos) {
-        let pixel_x = u32(round(pos.x));
-        let pixel_y = u32(round(pos.y));
-        let pixel_idx = pixel_y * ctx.canvas_width + pixel_x;
+    if bool(ctx.bool_enable_agent_deposit) && pixel_idx >= 0 && !is_blocked(agent.position) {
+        let idx = layer_offset(agent.species) + u32(pixel_idx);
 
-        // DEPOSIT
-        trail_map.data[pixel_idx] = clamp(trail_map.data[pixel_idx] + ctx.deposit_strength, 0.0, 1.0);
+        // DEPOSIT, added to the trail map by apply_deposits
+        atomicAdd(&deposits[idx], 1u);
     }
 }
 
+// Mirrors the position back onto the canvas and turns the agent away from the edges it crossed
+fn reflect_off_edges(agent: Agent) -> Agent {
+    var reflected = agent;
+    let max_position = vec2(f32(ctx.canvas_width - 1u), f32(ctx.canvas_height - 1u));
+
+    if agent.position.x < 0.0 {
+        reflected.position.x = -agent.position.x;
+        reflected.velocity.x = abs(agent.velocity.x);
+    }
+    if agent.position.x > max_position.x {
+        reflected.position.x = 2.0 * max_position.x - agent.position.x;
+        reflected.velocity.x = -abs(agent.velocity.x);
+    }
+    if agent.position.y < 0.0 {
+        reflected.position.y = -agent.position.y;
+        reflected.velocity.y = abs(agent.velocity.y);
+    }
+    if agent.position.y > max_position.y {
+        reflected.position.y = 2.0 * max_position.y - agent.position.y;
+        reflected.velocity.y = -abs(agent.velocity.y);
+    }
+
+    // Agents far off the canvas, e.g. placed there initially, come back at the edge
+    reflected.position = clamp(reflected.position, vec2(0.0, 0.0), max_position);
+    return reflected;
+}
+
+// Wraps a coordinate into [0, size)
+fn wrap(v: f32, size: f32) -> f32 {
+    return v - size * floor(v / size);
+}
+
+fn random_heading(rng_state: ptr<function, u32>) -> vec2<f32> {
+    return rotate_cw(random_float_in_range(0.0, 360.0, rng_state), vec2(1.0, 0.0));
+}
+
 @compute @workgroup_size(8,8,1)
 fn diffuse_and_decay(
     @builtin(workgroup_id) workgroup_id : vec3<u32>,
@@ -225,35 +389,261 @@ fn diffuse_and_decay(
     let x = idx % ctx.canvas_width;
     let y = idx / ctx.canvas_width;
 
+    // Every species' trail spreads and fades on its own
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        diffuse_and_decay_pixel(layer_offset(layer), x, y);
+    }
+}
+
+// First pass of a separable diffusion kernel, which blurs the rows of the trail map
+@compute @workgroup_size(8,8,1)
+fn diffuse_rows(
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let num_threads_per_workgroup: u32 = 8u * 8u * 1u;
+    let workgroup_index =
+        workgroup_id.x +
+        workgroup_id.y * num_workgroups.x +
+        workgroup_id.z * num_workgroups.x * num_workgroups.y;
+    let global_invocation_index = workgroup_index * num_threads_per_workgroup + local_invocation_index;
+
+    let pixel_idx = global_invocation_index;
+    if pixel_idx >= ctx.canvas_width * ctx.canvas_height || !bool(ctx.bool_enable_diffuse) {
+        return;
+    }
+
+    let x = pixel_idx % ctx.canvas_width;
+    let y = pixel_idx / ctx.canvas_width;
+
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        let offset = layer_offset(layer);
+        let radius = i32(diffusion_kernel.radius);
+
+        var sum = 0.0;
+        var total = 0.0;
+        var used = 0.0;
+        for (var i = -radius; i <= radius; i = i + 1) {
+            let weight = diffusion_kernel.weights[u32(i + radius)];
+            total = total + weight;
+
+            let neighbour_idx = diffusion_neighbour(x, y, i, 0);
+            if neighbour_idx < 0 {
+                continue;
+            }
+
+            sum = sum + weight * trail_map.data[offset + u32(neighbour_idx)];
+            used = used + weight;
+        }
+
+        diffused_rows.data[offset + pixel_idx] = renormalized(sum, total, used);
+    }
+}
+
+// Adds the deposits of the tick to the trail map and clears them for the next one
+@compute @workgroup_size(8,8,1)
+fn apply_deposits(
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let num_threads_per_workgroup: u32 = 8u * 8u * 1u;
+    let workgroup_index =
+        workgroup_id.x +
+        workgroup_id.y * num_workgroups.x +
+        workgroup_id.z * num_workgroups.x * num_workgroups.y;
+    let global_invocation_index = workgroup_index * num_threads_per_workgroup + local_invocation_index;
+
+    let pixel_idx = global_invocation_index;
+    if pixel_idx >= ctx.canvas_width * ctx.canvas_height {
+        return;
+    }
+
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        let idx = layer_offset(layer) + pixel_idx;
+        let deposit = f32(atomicExchange(&deposits[idx], 0u)) * species_table[layer].deposit_strength;
+        trail_map.data[idx] = clamp(trail_map.data[idx] + deposit, 0.0, 1.0);
+    }
+}
+
+fn diffuse_and_decay_pixel(offset: u32, x: u32, y: u32) {
+    let idx = offset + y * ctx.canvas_width + x;
+
+    // Walls hold no trail
+    if mask_layer[y * ctx.canvas_width + x] != 0u {
+        next_trail_map.data[idx] = 0.0;
+        return;
+    }
+
+    var value = trail_map.data[idx];
+
     // DIFFUSE
     if bool(ctx.bool_enable_diffuse) {
-        var sum: f32 = 0.0;
-        for (var i = -1; i <= 1; i = i + 1) {
-            for (var j = -1; j <= 1; j = j + 1) {
-                // Option 1: To handle the borders, skip pixels outside the canvas
-                let xi = i32(x) + i;
-                let yi = i32(y) + j;
-                if (xi < 0 || xi > i32(ctx.canvas_width) - 1 || yi < 0 || yi > i32(ctx.canvas_height) - 1) {
-                    continue;
-                }
-
-                // Option 2: To handle the borders, replicate the edge pixel values, meaning pixels outside the image have the value of the nearest edge pixel.
-                // let xi = clamp(i32(x) + i, 0, i32(ctx.canvas_width) - 1);
-                // let yi = clamp(i32(y) + j, 0, i32(ctx.canvas_height) - 1);
-
-                let xi_u = u32(xi);
-                let yi_u = u32(yi);
-
-                sum = sum + trail_map.data[yi_u * ctx.canvas_width + xi_u];
-            }
+        if bool(diffusion_kernel.separable) {
+            value = diffuse_column(offset, x, y);
+        } else {
+            value = diffuse(offset, x, y);
         }
-        trail_map.data[idx] = sum / 9.0;
     }
 
     // DECAY
     // Ratio that survives into the next iteration
     if bool(ctx.bool_enable_decay) {
-        trail_map.data[idx] = trail_map.data[idx] * (1.0 - ctx.decay_strength);
+        value = value * (1.0 - ctx.decay_strength);
+    }
+
+    // FEED: Food keeps stimulating the trail around it, and repellers suppress it
+    let food = food_layer[y * ctx.canvas_width + x];
+    next_trail_map.data[idx] = clamp(value + food * ctx.food_deposit_strength, 0.0, 1.0);
+}
+
+// Adds the brush stroke to every trail layer and to the food layer, fading out towards its rim.
+// Dispatched over the square of pixels around the stroke
+@compute @workgroup_size(8,8,1)
+fn paint(
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local_invocation_index: u32,
+) {
+    let num_threads_per_workgroup: u32 = 8u * 8u * 1u;
+    let workgroup_index =
+        workgroup_id.x +
+        workgroup_id.y * num_workgroups.x +
+        workgroup_id.z * num_workgroups.x * num_workgroups.y;
+    let global_invocation_index = workgroup_index * num_threads_per_workgroup + local_invocation_index;
+
+    // Must match BrushStroke::span in brush.rs
+    let reach = i32(ceil(max(brush.radius, 0.0)));
+    let span = u32(2 * reach + 1);
+    if global_invocation_index >= span * span {
+        return;
+    }
+
+    let centre = vec2<i32>(round(vec2(brush.x, brush.y)));
+    let offset = vec2(i32(global_invocation_index % span), i32(global_invocation_index / span)) - reach;
+    let pixel = centre + offset;
+    if any(pixel < vec2(0, 0)) || pixel.x >= i32(ctx.canvas_width) || pixel.y >= i32(ctx.canvas_height) {
+        return;
+    }
+
+    let distance = length(vec2<f32>(offset));
+    if distance > brush.radius {
+        return;
+    }
+
+    // Walls stay empty
+    let pixel_idx = u32(pixel.y) * ctx.canvas_width + u32(pixel.x);
+    if mask_layer[pixel_idx] != 0u {
+        return;
+    }
+
+    let weight = 1.0 - distance / (brush.radius + 1.0);
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        let idx = layer_offset(layer) + pixel_idx;
+        trail_map.data[idx] = clamp(trail_map.data[idx] + brush.trail_strength * weight, 0.0, 1.0);
+    }
+    food_layer[pixel_idx] = clamp(food_layer[pixel_idx] + brush.food_strength * weight, -1.0, 1.0);
+}
+
+// Applies the whole kernel around the pixel
+fn diffuse(offset: u32, x: u32, y: u32) -> f32 {
+    let radius = i32(diffusion_kernel.radius);
+    let size = 2 * radius + 1;
+
+    var sum = 0.0;
+    var total = 0.0;
+    var used = 0.0;
+    for (var j = -radius; j <= radius; j = j + 1) {
+        for (var i = -radius; i <= radius; i = i + 1) {
+            let weight = diffusion_kernel.weights[u32((j + radius) * size + i + radius)];
+            total = total + weight;
+
+            let neighbour_idx = diffusion_neighbour(x, y, i, j);
+            if neighbour_idx < 0 {
+                continue;
+            }
+
+            sum = sum + weight * trail_map.data[offset + u32(neighbour_idx)];
+            used = used + weight;
+        }
+    }
+
+    return renormalized(sum, total, used);
+}
+
+// Second pass of a separable kernel, which blurs the column of the pixel in the blurred rows
+fn diffuse_column(offset: u32, x: u32, y: u32) -> f32 {
+    let radius = i32(diffusion_kernel.radius);
+
+    var sum = 0.0;
+    var total = 0.0;
+    var used = 0.0;
+    for (var j = -radius; j <= radius; j = j + 1) {
+        let weight = diffusion_kernel.weights[u32(j + radius)];
+        total = total + weight;
+
+        let neighbour_idx = diffusion_neighbour(x, y, 0, j);
+        if neighbour_idx < 0 {
+            continue;
+        }
+
+        sum = sum + weight * diffused_rows.data[offset + u32(neighbour_idx)];
+        used = used + weight;
+    }
+
+    return renormalized(sum, total, used);
+}
+
+// Index within a layer of the pixel diffusion takes for the neighbour `(i, j)` away from `(x, y)`,
+// or -1 when there is none: outside the canvas, trail is empty, and it does not spread through walls
+fn diffusion_neighbour(x: u32, y: u32, i: i32, j: i32) -> i32 {
+    let xi = edge_neighbour(i32(x) + i, i32(ctx.canvas_width));
+    let yi = edge_neighbour(i32(y) + j, i32(ctx.canvas_height));
+
+    if (xi < 0 || xi > i32(ctx.canvas_width) - 1 || yi < 0 || yi > i32(ctx.canvas_height) - 1) {
+        return -1;
+    }
+
+    let neighbour_idx = u32(yi) * ctx.canvas_width + u32(xi);
+    if mask_layer[neighbour_idx] != 0u {
+        return -1;
+    }
+
+    return i32(neighbour_idx);
+}
+
+// Weighted sum of the neighbours diffusion took, of which only `used` out of the `total` weight
+// counted. Renormalizing makes up for the ones left out.
+fn renormalized(sum: f32, total: f32, used: f32) -> f32 {
+    if ctx.diffusion_edge_mode == DIFFUSION_EDGE_RENORMALIZE && used != 0.0 {
+        return sum * total / used;
+    }
+    return sum;
+}
+
+// Coordinate of the pixel diffusion takes for the neighbour at `i`, which may lie beyond the edges
+// of a canvas `size` pixels across
+fn edge_neighbour(i: i32, size: i32) -> i32 {
+    switch (ctx.diffusion_edge_mode) {
+        case DIFFUSION_EDGE_CLAMP: {
+            return clamp(i, 0, size - 1);
+        }
+        case DIFFUSION_EDGE_WRAP: {
+            return (i % size + size) % size;
+        }
+        case DIFFUSION_EDGE_MIRROR: {
+            if i < 0 {
+                return clamp(-i, 0, size - 1);
+            }
+            if i > size - 1 {
+                return clamp(2 * (size - 1) - i, 0, size - 1);
+            }
+            return i;
+        }
+        default: {
+            return i;
+        }
     }
 }
 
@@ -283,43 +673,121 @@ fn sense(agent: Agent, sensor_angle_ccw_degrees: f32) -> f32 {
     let facing_direction = normalize(agent.velocity);
     let rotated_facing_direction: vec2<f32> = rotate_ccw(sensor_angle_ccw_degrees, facing_direction);
     // Sensor position:
-    let sensor: vec2<f32> = agent.position + rotated_facing_direction * ctx.sensor_distance;
-    return deposit_strength_at(sensor);
+    let sensor: vec2<f32> = agent.position + rotated_facing_direction * species_table[agent.species].sensor_distance;
+
+    // Weigh the trail of every species by how much the agent's species is drawn to it
+    var value = 0.0;
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        value = value + species_table[agent.species].attraction[layer] * deposit_strength_at(layer, sensor);
+    }
+    return value;
 }
 
-fn deposit_strength_at(pos: vec2<f32>) -> f32 {
-    if is_out_of_bounds(pos) {
+fn deposit_strength_at(layer: u32, pos: vec2<f32>) -> f32 {
+    let pixel_idx = pixel_index(pos);
+    if pixel_idx < 0 {
         return 0.0;
     }
 
-    let pos_x: u32 = u32(round(pos.x));
-    let pos_y: u32 = u32(round(pos.y));
-    let pos_idx: u32 = pos_y * ctx.canvas_width + pos_x;
-    return trail_map.data[pos_idx];
+    return trail_map.data[layer_offset(layer) + u32(pixel_idx)];
 }
 
-fn rand_sign(seed: f32) -> f32 {
-    return sign(random_float_in_range(-1.0, 1.0, seed));
+// Whether an obstacle covers the pixel at the position. Outside the canvas is left to the borders.
+fn is_blocked(pos: vec2<f32>) -> bool {
+    let pixel_idx = pixel_index(pos);
+    if pixel_idx < 0 {
+        return false;
+    }
+
+    return mask_layer[u32(pixel_idx)] != 0u;
 }
 
-fn random_float_in_range(min: f32, max: f32, seed: f32) -> f32 {
-    let seed_u32 = bitcast<u32>(seed);
+// Index of the pixel nearest to the position within a layer, or -1 when the position is off the
+// canvas. On a toroidal canvas, positions off one edge continue on the opposite one.
+fn pixel_index(pos: vec2<f32>) -> i32 {
+    if ctx.boundary_mode == BOUNDARY_WRAP {
+        // Positions just short of the far edge round up onto it, which is the near edge again
+        let pixel_x = u32(round(wrap(pos.x, f32(ctx.canvas_width)))) % ctx.canvas_width;
+        let pixel_y = u32(round(wrap(pos.y, f32(ctx.canvas_height)))) % ctx.canvas_height;
+        return i32(pixel_y * ctx.canvas_width + pixel_x);
+    }
 
+    if is_out_of_bounds(pos) {
+        return -1;
+    }
+
+    return i32(u32(round(pos.y)) * ctx.canvas_width + u32(round(pos.x)));
+}
+
+// Start of the trail layer of a species in the trail map
+fn layer_offset(layer: u32) -> u32 {
+    return layer * ctx.canvas_width * ctx.canvas_height;
+}
+
+// Every species in a colour of its own, added up
+fn mix_species(position: vec2<f32>) -> vec3<f32> {
+    var color = vec3(0.0, 0.0, 0.0);
+    for (var layer = 0u; layer < ctx.number_of_species; layer = layer + 1u) {
+        let v = sample_trail_map(layer, position);
+        if bool(ctx.bool_enable_color) {
+            color = color + v * species_color(layer);
+        } else {
+            color = color + vec3(v, v, v);
+        }
+    }
+    return min(color, vec3(1.0, 1.0, 1.0));
+}
+
+// Trail of one layer at a position on the canvas, between pixel centres as the view samples it
+fn sample_trail_map(layer: u32, position: vec2<f32>) -> f32 {
+    let offset = layer_offset(layer);
+    let last = vec2(i32(ctx.canvas_width) - 1, i32(ctx.canvas_height) - 1);
+
+    if view.sampling == SAMPLING_NEAREST {
+        let pixel = min(vec2<i32>(position), last);
+        return trail_map.data[offset + u32(pixel.y) * ctx.canvas_width + u32(pixel.x)];
+    }
+
+    // Pixel centres lie at half pixels, and the edges repeat the outermost pixels
+    let corner = position - 0.5;
+    let t = fract(corner);
+    let p0 = clamp(vec2<i32>(floor(corner)), vec2(0, 0), last);
+    let p1 = clamp(vec2<i32>(floor(corner)) + 1, vec2(0, 0), last);
+
+    let v00 = trail_map.data[offset + u32(p0.y) * ctx.canvas_width + u32(p0.x)];
+    let v10 = trail_map.data[offset + u32(p0.y) * ctx.canvas_width + u32(p1.x)];
+    let v01 = trail_map.data[offset + u32(p1.y) * ctx.canvas_width + u32(p0.x)];
+    let v11 = trail_map.data[offset + u32(p1.y) * ctx.canvas_width + u32(p1.x)];
+
+    return mix(mix(v00, v10, t.x), mix(v01, v11, t.x), t.y);
+}
+
+fn species_color(layer: u32) -> vec3<f32> {
+    switch (layer) {
+        case 0u: { return vec3(246.0, 255.0, 0.0) / 255.0; }
+        case 1u: { return vec3(0.0, 221.0, 255.0) / 255.0; }
+        case 2u: { return vec3(255.0, 0.0, 170.0) / 255.0; }
+        default: { return vec3(255.0, 128.0, 0.0) / 255.0; }
+    }
+}
+
+fn rand_sign(rng_state: ptr<function, u32>) -> f32 {
+    return sign(random_float_in_range(-1.0, 1.0, rng_state));
+}
+
+fn random_float_in_range(min: f32, max: f32, rng_state: ptr<function, u32>) -> f32 {
     // Convert the u32 to a float in the range [0.0, 1.0)
-    let random_float = f32(rand_u32(seed_u32)) / f32(0xFFFFFFFFu);
+    let random_float = f32(rand_u32(rng_state)) / f32(0xFFFFFFFFu);
 
     // Scale and shift to the desired range
     return min + random_float * (max - min);
 }
 
-fn seed_from_agent(agent: Agent) -> f32 {
-    let seed = agent.position.x + agent.position.y + abs(agent.velocity.x);
-    return seed;
-}
-
-fn rand_u32(seed: u32) -> u32 {
-    var h = seed * 747796405u + 2891336453u;
-    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+// PCG (RXS-M-XS variant): outputs a permutation of the current state and advances it
+fn rand_u32(rng_state: ptr<function, u32>) -> u32 {
+    let state = *rng_state;
+    *rng_state = state * 747796405u + 2891336453u;
+    let h = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
     return (h >> 22u) ^ h;
 }
 
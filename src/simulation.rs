@@ -0,0 +1,414 @@
+use crate::{
+    agent::Agent,
+    brush::BrushStroke,
+    checkpoint::{self, Checkpoint},
+    device::Device,
+    environment::{self, Environment},
+    parameters::{Parameters, ShaderParameters},
+    pipelines::Pipelines,
+    resources::Resources,
+};
+
+// Must match what is in the shader code
+const WORKGROUP_SIZE_X: u32 = 8;
+const WORKGROUP_SIZE_Y: u32 = 8;
+const WORKGROUP_SIZE_Z: u32 = 1;
+
+/// The simulation without any presentation attached to it.
+///
+/// Owns the device, the GPU buffers and the compute pipelines, so it can be stepped on machines
+/// without a display. Rendering to a window is layered on top of it (see `run`).
+pub struct Simulation {
+    params: Parameters,
+    pub device: Device,
+    pub resources: Resources,
+    pub pipelines: Pipelines,
+    /// Which of the two trail buffers holds the current trail map. Diffusion writes the other one,
+    /// which then takes over.
+    current_trail_layer: usize,
+}
+
+impl Simulation {
+    /// Creates a simulation on an adapter picked without regard to any surface.
+    pub async fn headless(params: Parameters) -> Result<Self, environment::Error> {
+        Self::new(Device::headless().await, params)
+    }
+
+    /// Fails if the environment (e.g. a food image) cannot be loaded.
+    pub fn new(device: Device, mut params: Parameters) -> Result<Self, environment::Error> {
+        params.prepare();
+
+        let environment = Environment::new(&params)?;
+        let resources = Resources::new(&device.device, &params, &environment);
+
+        Ok(Self::with_resources(device, params, resources))
+    }
+
+    /// Picks up a run from where the checkpoint was taken.
+    pub fn from_checkpoint(
+        device: Device,
+        checkpoint: &Checkpoint,
+    ) -> Result<Self, environment::Error> {
+        // The environment is static, so it is rebuilt rather than stored in the checkpoint
+        let environment = Environment::new(&checkpoint.params)?;
+        let resources = Resources::with_state(
+            &device.device,
+            &checkpoint.params,
+            &environment,
+            &checkpoint.agents,
+            &checkpoint.trail_map,
+        );
+
+        Ok(Self::with_resources(
+            device,
+            checkpoint.params.clone(),
+            resources,
+        ))
+    }
+
+    fn with_resources(device: Device, params: Parameters, resources: Resources) -> Self {
+        let pipelines = Pipelines::new(&device.device, &resources);
+
+        Self {
+            params,
+            device,
+            resources,
+            pipelines,
+            current_trail_layer: 0,
+        }
+    }
+
+    pub fn params(&self) -> &Parameters {
+        &self.params
+    }
+
+    /// Replaces the shader parameters and uploads them to the GPU, taking effect on the next tick.
+    ///
+    /// The canvas size and number of species are fixed when the buffers are created, so those are
+    /// kept as they are.
+    pub fn set_shader_parameters(&mut self, shader_parameters: ShaderParameters) {
+        self.params.shader_parameters = ShaderParameters {
+            canvas_width: self.params.shader_parameters.canvas_width,
+            canvas_height: self.params.shader_parameters.canvas_height,
+            number_of_species: self.params.shader_parameters.number_of_species,
+            ..shader_parameters
+        };
+
+        self.upload_shader_parameters();
+    }
+
+    fn upload_shader_parameters(&self) {
+        self.device.queue.write_buffer(
+            &self.resources.shader_context.buffer,
+            0,
+            bytemuck::cast_slice(&[self.params.shader_parameters]),
+        );
+
+        // The first species follows the shader parameters
+        self.device.queue.write_buffer(
+            &self.resources.species_table,
+            0,
+            bytemuck::cast_slice(&self.params.species_table()),
+        );
+    }
+
+    /// Changes the shader parameters in place, e.g. `simulation.update_shader_parameters(|p| p.randomize())`.
+    pub fn update_shader_parameters(&mut self, update: impl FnOnce(&mut ShaderParameters)) {
+        let mut shader_parameters = self.params.shader_parameters;
+        update(&mut shader_parameters);
+        self.set_shader_parameters(shader_parameters);
+    }
+
+    /// Rewinds (or forwards) the running simulation to a checkpoint of the same number of agents
+    /// and number of species, keeping the parameters currently in use to branch off from it.
+    ///
+    /// A checkpoint taken before the canvas was resampled or restarted at another size is resized
+    /// to the canvas as it is now.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), checkpoint::Error> {
+        let checkpoint = checkpoint.clone().fitted_to(self.params.clone())?;
+
+        let queue = &self.device.queue;
+        queue.write_buffer(
+            &self.resources.data_layer.buffer,
+            0,
+            bytemuck::cast_slice(&checkpoint.agents),
+        );
+        queue.write_buffer(
+            &self.resources.trail_layer.buffers[self.current_trail_layer],
+            0,
+            bytemuck::cast_slice(&checkpoint.trail_map),
+        );
+
+        self.params = checkpoint.params;
+        self.upload_shader_parameters();
+
+        Ok(())
+    }
+
+    /// Carries the running simulation over to a canvas of another size (see
+    /// `Checkpoint::resized`), recreating the buffers and pipelines for it.
+    ///
+    /// Anything rendering the simulation has to be rebuilt against the new resources.
+    pub fn resize_canvas(&mut self, width: u32, height: u32) -> Result<(), environment::Error> {
+        let ctx = &self.params.shader_parameters;
+        if (width, height) == (ctx.canvas_width, ctx.canvas_height) {
+            return Ok(());
+        }
+
+        let checkpoint = Checkpoint::capture(self).resized(width, height);
+
+        let environment = Environment::new(&checkpoint.params)?;
+        let resources = Resources::with_state(
+            &self.device.device,
+            &checkpoint.params,
+            &environment,
+            &checkpoint.agents,
+            &checkpoint.trail_map,
+        );
+        self.replace_resources(checkpoint.params, resources);
+
+        Ok(())
+    }
+
+    /// Starts over from a fresh initial distribution on the same device, e.g. after changing the
+    /// initial conditions, number of agents or canvas size.
+    ///
+    /// Anything rendering the simulation has to be rebuilt against the new resources.
+    pub fn restart(&mut self, mut params: Parameters) -> Result<(), environment::Error> {
+        params.prepare();
+        params.tick = 0;
+
+        let environment = Environment::new(&params)?;
+        let resources = Resources::new(&self.device.device, &params, &environment);
+        self.replace_resources(params, resources);
+
+        Ok(())
+    }
+
+    fn replace_resources(&mut self, params: Parameters, resources: Resources) {
+        self.pipelines = Pipelines::new(&self.device.device, &resources);
+        self.resources = resources;
+        self.params = params;
+        self.current_trail_layer = 0;
+    }
+
+    pub fn set_target_ticks_per_second(&mut self, target_ticks_per_second: f32) {
+        self.params.target_ticks_per_second = target_ticks_per_second;
+    }
+
+    /// Advances the simulation by one tick: diffuse and decay the trail map, then let every agent
+    /// sense, move and deposit, and finally add the deposits to the trail map.
+    pub fn step(&mut self) {
+        // Start a new command encoder
+        let mut command_encoder =
+            self.device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("command-encoder"),
+                });
+
+        self.encode_step(&mut command_encoder);
+
+        let command_buffer = command_encoder.finish();
+        self.device.queue.submit(Some(command_buffer));
+
+        self.current_trail_layer = 1 - self.current_trail_layer;
+
+        self.params.tick = self.params.tick.wrapping_add(1);
+    }
+
+    /// Records the compute passes of one tick into `command_encoder` without submitting them.
+    ///
+    /// The tick is not advanced and the trail buffers are not swapped, so encoding it again
+    /// repeats the same tick.
+    pub fn encode_step(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        let diffused_trail_layer = 1 - self.current_trail_layer;
+
+        // A separable kernel first blurs the rows, which diffusion then continues from
+        if self.params.diffusion_kernel.is_separable() {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("diffuse-rows-cp"),
+                    timestamp_writes: None,
+                });
+
+            compute_pass.set_pipeline(&self.pipelines.diffuse_rows);
+            self.set_bind_groups_reading(&mut compute_pass, self.current_trail_layer);
+
+            let ctx = &self.params.shader_parameters;
+            dispatch_threads(&mut compute_pass, ctx.canvas_width * ctx.canvas_height);
+        }
+
+        // Diffuse and decay from the current trail buffer into the other one
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("diffuse-and-decay-cp"),
+                    timestamp_writes: None,
+                });
+
+            compute_pass.set_pipeline(&self.pipelines.diffuse_and_decay);
+            self.set_bind_groups_reading(&mut compute_pass, self.current_trail_layer);
+
+            let ctx = &self.params.shader_parameters;
+            dispatch_threads(&mut compute_pass, ctx.canvas_width * ctx.canvas_height);
+        }
+
+        // Move agents and deposit on the diffused trail map
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("agent-sense-move-deposit-cp"),
+                    timestamp_writes: None,
+                });
+
+            compute_pass.set_pipeline(&self.pipelines.agent_sense_move_deposit);
+            self.set_bind_groups_reading(&mut compute_pass, diffused_trail_layer);
+
+            let number_of_active_agents = self
+                .params
+                .shader_parameters
+                .number_of_active_agents
+                .min(self.params.number_of_agents);
+
+            dispatch_threads(&mut compute_pass, number_of_active_agents);
+        }
+
+        // Add up the deposits
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("apply-deposits-cp"),
+                    timestamp_writes: None,
+                });
+
+            compute_pass.set_pipeline(&self.pipelines.apply_deposits);
+            self.set_bind_groups_reading(&mut compute_pass, diffused_trail_layer);
+
+            let ctx = &self.params.shader_parameters;
+            dispatch_threads(&mut compute_pass, ctx.canvas_width * ctx.canvas_height);
+        }
+    }
+
+    /// Paints a brush stroke into the trail map and food layer right away, between ticks.
+    ///
+    /// Painted food lasts until the environment is rebuilt, e.g. when the grid is resampled.
+    pub fn paint(&mut self, stroke: &BrushStroke) {
+        self.device
+            .queue
+            .write_buffer(&self.resources.brush, 0, bytemuck::cast_slice(&[*stroke]));
+
+        let mut command_encoder =
+            self.device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("paint-command-encoder"),
+                });
+
+        {
+            let mut compute_pass =
+                command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("paint-cp"),
+                    timestamp_writes: None,
+                });
+
+            compute_pass.set_pipeline(&self.pipelines.paint);
+            self.set_bind_groups(&mut compute_pass);
+
+            let span = stroke.span();
+            dispatch_threads(&mut compute_pass, span * span);
+        }
+
+        self.device.queue.submit(Some(command_encoder.finish()));
+    }
+
+    /// Copies the agent buffer back to the CPU. Blocks until the GPU is done with it.
+    pub fn read_agents(&self) -> Vec<Agent> {
+        bytemuck::pod_collect_to_vec(&self.read_buffer(&self.resources.data_layer.buffer))
+    }
+
+    /// Copies the trail map back to the CPU. Blocks until the GPU is done with it.
+    pub fn read_trail_map(&self) -> Vec<f32> {
+        bytemuck::pod_collect_to_vec(
+            &self.read_buffer(&self.resources.trail_layer.buffers[self.current_trail_layer]),
+        )
+    }
+
+    fn read_buffer(&self, buffer: &wgpu::Buffer) -> Vec<u8> {
+        let device = &self.device.device;
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging-buffer"),
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("readback-command-encoder"),
+        });
+        command_encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+        self.device.queue.submit(Some(command_encoder.finish()));
+
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range().to_vec();
+        staging_buffer.unmap();
+
+        data
+    }
+
+    /// Binds the simulation resources in the order the shader declares them.
+    pub fn set_bind_groups<'pass>(&'pass self, pass: &mut impl BindGroups<'pass>) {
+        self.set_bind_groups_reading(pass, self.current_trail_layer);
+    }
+
+    /// Binds the simulation resources with the given trail buffer as the trail map.
+    fn set_bind_groups_reading<'pass>(
+        &'pass self,
+        pass: &mut impl BindGroups<'pass>,
+        trail_layer: usize,
+    ) {
+        pass.set_bind_group(0, &self.resources.shader_context.bind_group);
+        pass.set_bind_group(1, &self.resources.data_layer.bind_group);
+        pass.set_bind_group(2, &self.resources.trail_layer.bind_groups[trail_layer]);
+    }
+}
+
+/// Dispatches at least `number_of_threads` invocations of a kernel that works on one item per
+/// thread, e.g. one agent or one pixel.
+fn dispatch_threads(compute_pass: &mut wgpu::ComputePass, number_of_threads: u32) {
+    // Lay the items out in x and y so they can be mapped to shader workgroups
+    let threads_per_workgroup = WORKGROUP_SIZE_X * WORKGROUP_SIZE_Y * WORKGROUP_SIZE_Z;
+
+    let workgroups_needed = number_of_threads.div_ceil(threads_per_workgroup);
+
+    const NUMBER_OF_WORKGROUPS_X: u32 = 32;
+    let number_of_workgroups_y = workgroups_needed.div_ceil(NUMBER_OF_WORKGROUPS_X);
+    let number_of_workgroups_z = 1;
+
+    compute_pass.dispatch_workgroups(
+        NUMBER_OF_WORKGROUPS_X,
+        number_of_workgroups_y,
+        number_of_workgroups_z,
+    );
+}
+
+/// Common ground of compute and render passes, so both can share `Simulation::set_bind_groups`.
+pub trait BindGroups<'pass> {
+    fn set_bind_group(&mut self, index: u32, bind_group: &'pass wgpu::BindGroup);
+}
+
+impl<'pass> BindGroups<'pass> for wgpu::ComputePass<'pass> {
+    fn set_bind_group(&mut self, index: u32, bind_group: &'pass wgpu::BindGroup) {
+        wgpu::ComputePass::set_bind_group(self, index, bind_group, &[]);
+    }
+}
+
+impl<'pass> BindGroups<'pass> for wgpu::RenderPass<'pass> {
+    fn set_bind_group(&mut self, index: u32, bind_group: &'pass wgpu::BindGroup) {
+        wgpu::RenderPass::set_bind_group(self, index, bind_group, &[]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Frame sinks that encode a whole run into a single stream instead of loose images.
+
+use std::{borrow::Cow, io};
+
+use crate::{
+    capture::{color_srgb, render_frame, resample, FrameSink, FrameSize},
+    parameters::ShaderParameters,
+};
+
+/// Writes a YUV4MPEG2 stream, which most encoders accept on stdin, e.g.
+/// `physarum render --format y4m --stdout | ffmpeg -i - physarum.mp4`.
+pub struct Y4mWriter<W: io::Write> {
+    writer: W,
+    size: Option<FrameSize>,
+    frames_per_second: u32,
+    header_written: bool,
+}
+
+impl<W: io::Write> Y4mWriter<W> {
+    /// Frames are written at canvas size unless `size` is given.
+    pub fn new(writer: W, size: Option<FrameSize>, frames_per_second: u32) -> Self {
+        Self {
+            writer,
+            size,
+            frames_per_second,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: io::Write> FrameSink for Y4mWriter<W> {
+    fn write_frame(&mut self, ctx: &ShaderParameters, trail_map: &[f32]) -> io::Result<()> {
+        let size = *self.size.get_or_insert(FrameSize::of_canvas(ctx));
+
+        if !self.header_written {
+            // Progressive, square pixels, no chroma subsampling
+            writeln!(
+                self.writer,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                size.width, size.height, self.frames_per_second
+            )?;
+            self.header_written = true;
+        }
+
+        let frame = render_frame(ctx, &resample(ctx, trail_map, size), size);
+
+        let pixel_count = (size.width * size.height) as usize;
+        let mut planes = vec![0; 3 * pixel_count];
+        for (i, pixel) in frame.pixels().enumerate() {
+            let [y, u, v] = rgb_to_yuv(pixel.0);
+            planes[i] = y;
+            planes[pixel_count + i] = u;
+            planes[2 * pixel_count + i] = v;
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Studio range BT.601, which is what players assume for Y4M without further tags.
+fn rgb_to_yuv([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let [r, g, b] = [r, g, b].map(|c| f32::from(c) / 255.0);
+
+    let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+    let u = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+    let v = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+
+    [y, u, v].map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Writes a looping animated GIF.
+///
+/// With a single species, trail map values map onto a one-dimensional colour ramp, so the palette
+/// is the active gradient sampled at 256 points and every pixel is simply its quantized value.
+/// Several species mix into arbitrary colours, so those frames are quantized to a palette of their
+/// own.
+pub struct GifWriter<W: io::Write> {
+    writer: Option<W>,
+    encoder: Option<gif::Encoder<W>>,
+    global_palette: Vec<u8>,
+    size: Option<FrameSize>,
+    frames_per_second: u32,
+}
+
+impl<W: io::Write> GifWriter<W> {
+    /// Frames are written at canvas size unless `size` is given.
+    pub fn new(writer: W, size: Option<FrameSize>, frames_per_second: u32) -> Self {
+        Self {
+            writer: Some(writer),
+            encoder: None,
+            global_palette: Vec::new(),
+            size,
+            frames_per_second,
+        }
+    }
+}
+
+impl<W: io::Write> FrameSink for GifWriter<W> {
+    fn write_frame(&mut self, ctx: &ShaderParameters, trail_map: &[f32]) -> io::Result<()> {
+        let size = *self.size.get_or_insert(FrameSize::of_canvas(ctx));
+
+        if size.width > u32::from(u16::MAX) || size.height > u32::from(u16::MAX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "GIF frames can be at most 65535 pixels wide and high",
+            ));
+        }
+
+        let palette: Vec<u8> = (0..=255u8)
+            .flat_map(|i| color_srgb(ctx, f32::from(i) / 255.0))
+            .collect();
+
+        if self.encoder.is_none() {
+            let writer = self.writer.take().unwrap();
+            let mut encoder =
+                gif::Encoder::new(writer, size.width as u16, size.height as u16, &palette)
+                    .map_err(io::Error::other)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(io::Error::other)?;
+            self.encoder = Some(encoder);
+            self.global_palette = palette.clone();
+        }
+
+        // GIF delays are whole centiseconds, and many players take 0 to mean their default speed
+        let delay = ((100.0 / self.frames_per_second as f32).round() as u16).max(1);
+
+        let frame = if ctx.number_of_species > 1 && ctx.bool_enable_render_trail_map != 0 {
+            let rgb = render_frame(ctx, &resample(ctx, trail_map, size), size);
+            gif::Frame {
+                delay,
+                ..gif::Frame::from_rgb_speed(size.width as u16, size.height as u16, &rgb, 10)
+            }
+        } else {
+            let pixels: Vec<u8> = resample(ctx, trail_map, size)
+                .iter()
+                .take((size.width * size.height) as usize)
+                .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+                .collect();
+
+            // The gradient can change between frames (e.g. colour toggled off), in which case the
+            // frame carries its own palette
+            gif::Frame {
+                width: size.width as u16,
+                height: size.height as u16,
+                delay,
+                palette: (palette != self.global_palette).then_some(palette),
+                buffer: Cow::Owned(pixels),
+                ..Default::default()
+            }
+        };
+
+        self.encoder
+            .as_mut()
+            .unwrap()
+            .write_frame(&frame)
+            .map_err(io::Error::other)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.encoder.take() {
+            Some(encoder) => encoder.into_inner()?.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Showing the trail map on a surface of any size.
+//!
+//! The canvas is scaled to fit the surface, keeping its aspect ratio, and the remaining bars are
+//! left black. Unless the viewer is asked to resample the grid on resize, the grid size of the
+//! simulation does not depend on the window at all.
+//!
+//! A camera on top of that pans and zooms into the canvas, to inspect fine structure up close.
+
+use wgpu::util::DeviceExt;
+
+use crate::{parameters::ShaderParameters, resources::Resource};
+
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 100.0;
+
+/// Uniform the fragment shader reads next to the simulation resources.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Zeroable, bytemuck::NoUninit)]
+pub struct ViewParameters {
+    pub surface_width: u32,
+    pub surface_height: u32,
+    /// Magnification on top of fitting the canvas into the surface.
+    pub zoom: f32,
+    pub sampling: Sampling,
+    /// Offset of the canvas from the middle of the surface, in canvas pixels.
+    pub translate: [f32; 2],
+}
+
+/// How the trail map is read between the centres of its pixels.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, bytemuck::Zeroable, bytemuck::NoUninit)]
+pub enum Sampling {
+    /// Every canvas pixel shows as a sharp square.
+    #[default]
+    Nearest = 0,
+    /// Interpolates between the four nearest canvas pixels.
+    Bilinear = 1,
+}
+
+impl ViewParameters {
+    /// Surface pixels per canvas pixel.
+    fn scale(&self, ctx: &ShaderParameters) -> f32 {
+        let fit = (self.surface_width as f32 / ctx.canvas_width as f32)
+            .min(self.surface_height as f32 / ctx.canvas_height as f32);
+        fit * self.zoom
+    }
+
+    /// Canvas position shown at the surface position `(x, y)`, which may lie off the canvas.
+    pub fn canvas_position(&self, ctx: &ShaderParameters, x: f32, y: f32) -> [f32; 2] {
+        // Must match fragment_main in the shader
+        let scale = self.scale(ctx);
+        [
+            (x - self.surface_width as f32 / 2.0) / scale + ctx.canvas_width as f32 / 2.0
+                - self.translate[0],
+            (y - self.surface_height as f32 / 2.0) / scale + ctx.canvas_height as f32 / 2.0
+                - self.translate[1],
+        ]
+    }
+
+    /// Moves the canvas along with the cursor, by a distance in surface pixels.
+    pub fn pan(&mut self, ctx: &ShaderParameters, dx: f32, dy: f32) {
+        let scale = self.scale(ctx);
+        self.translate[0] += dx / scale;
+        self.translate[1] += dy / scale;
+    }
+
+    /// Zooms in by `factor`, or out for factors below 1, keeping the canvas pixel under the
+    /// surface position `(x, y)` in place.
+    pub fn zoom_at(&mut self, ctx: &ShaderParameters, factor: f32, x: f32, y: f32) {
+        let old_scale = self.scale(ctx);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let new_scale = self.scale(ctx);
+
+        let from_middle = [
+            x - self.surface_width as f32 / 2.0,
+            y - self.surface_height as f32 / 2.0,
+        ];
+        for (translate, from_middle) in self.translate.iter_mut().zip(from_middle) {
+            *translate += from_middle * (1.0 / new_scale - 1.0 / old_scale);
+        }
+    }
+
+    /// Shows the whole canvas again.
+    pub fn reset_camera(&mut self) {
+        self.zoom = 1.0;
+        self.translate = [0.0, 0.0];
+    }
+
+    pub fn toggle_sampling(&mut self) {
+        self.sampling = match self.sampling {
+            Sampling::Nearest => Sampling::Bilinear,
+            Sampling::Bilinear => Sampling::Nearest,
+        };
+    }
+}
+
+pub struct View {
+    pub params: ViewParameters,
+    pub resource: Resource,
+}
+
+impl View {
+    pub fn new(device: &wgpu::Device, surface_width: u32, surface_height: u32) -> Self {
+        let params = ViewParameters {
+            surface_width,
+            surface_height,
+            zoom: 1.0,
+            sampling: Sampling::default(),
+            translate: [0.0, 0.0],
+        };
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("view"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("view-bind-group-layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<ViewParameters>() as u64
+                    ),
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("view-bind-group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            params,
+            resource: Resource {
+                buffer,
+                bind_group,
+                bind_group_layout,
+            },
+        }
+    }
+
+    /// Follows the surface to a new size, taking effect with the next frame rendered.
+    pub fn resize(&mut self, queue: &wgpu::Queue, surface_width: u32, surface_height: u32) {
+        self.update(queue, |params| {
+            params.surface_width = surface_width;
+            params.surface_height = surface_height;
+        });
+    }
+
+    /// Changes the view parameters in place and uploads them, e.g. to move the camera.
+    pub fn update(&mut self, queue: &wgpu::Queue, update: impl FnOnce(&mut ViewParameters)) {
+        update(&mut self.params);
+
+        queue.write_buffer(
+            &self.resource.buffer,
+            0,
+            bytemuck::cast_slice(&[self.params]),
+        );
+    }
+}